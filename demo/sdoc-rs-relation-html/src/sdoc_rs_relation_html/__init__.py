@@ -29,7 +29,9 @@ class Relation:
 def load_relations(path: Path, hash: str) -> Tuple[str, List[Relation]]:
     file = ""
     relations: List[Relation] = []
-    elements = json.loads(path.read_text())
+    document = json.loads(path.read_text())
+    # Version 0 output is a bare array, later versions wrap it in an object with a "version"
+    elements = document if isinstance(document, list) else document["files"]
     matching = 0
     for element in elements:
         if element["hash"]["sha256"] != hash:
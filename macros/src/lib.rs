@@ -0,0 +1,29 @@
+//! The `#[sdoc::relation(...)]` attribute, which links an item to StrictDoc requirements
+//! without putting markers in its doc comments (and so in the `rustdoc` output).
+//!
+//! The attribute does nothing: it is found by `sdoc-rs-relations`, which reads the source code,
+//! and is stripped here so that the code still compiles. Depend on this crate as `sdoc`:
+//!
+//! ```toml
+//! [dependencies]
+//! sdoc = { package = "sdoc-rs-relations-macros", version = "1" }
+//! ```
+//!
+//! and then:
+//!
+//! ```
+//! #[sdoc::relation("REQ-1", "REQ-2", role = "implements")]
+//! fn thing() {}
+//! # use sdoc_rs_relations_macros as sdoc;
+//! ```
+
+use proc_macro::TokenStream;
+
+/// Link the item to one or more requirements, given as string identifiers, followed by any
+/// `key = "value"` attributes such as `role = "implements"` or `scope = "function"`.
+///
+/// The item is returned unchanged.
+#[proc_macro_attribute]
+pub fn relation(_arguments: TokenStream, item: TokenStream) -> TokenStream {
+    item
+}
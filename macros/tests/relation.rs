@@ -0,0 +1,25 @@
+use sdoc_rs_relations_macros as sdoc;
+
+#[sdoc::relation("REQ-1", role = "implements")]
+fn answer() -> u32 {
+    42
+}
+
+#[sdoc::relation("REQ-2", "REQ-3")]
+struct Thing {
+    value: u32,
+}
+
+#[sdoc::relation("REQ-4")]
+impl Thing {
+    #[sdoc::relation("REQ-5", scope = "function")]
+    fn value(&self) -> u32 {
+        self.value
+    }
+}
+
+#[test]
+fn relation_leaves_items_unchanged() {
+    assert_eq!(answer(), 42);
+    assert_eq!(Thing { value: 7 }.value(), 7);
+}
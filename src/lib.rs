@@ -1,2 +1,21 @@
+//! Parse Rust source trees for [StrictDoc](https://strictdoc.readthedocs.io/) `@relation` markers
+//! to yield source code spans.
+//!
+//! The `sdoc-rs-relations` binary is a thin wrapper around this library, so the same
+//! extraction can be embedded in build tooling and tests without re-parsing its JSON output.
+//!
+//! ```
+//! let src = "/// Does a thing, see @relation(REQ-1)\nfn thing() {}\n";
+//! let relations = sdoc_rs_relations::find_relations_in_str(&"thing.rs", src).unwrap();
+//! assert_eq!(relations.relations[0].ident, "REQ-1");
+//! ```
+
 pub mod parse;
 pub mod sdoc;
+
+pub use parse::tree::Scope;
+pub use parse::{Columns, Keyword, LineColumn, Span};
+pub use sdoc::{
+    find_relations, find_relations_in_crate, find_relations_in_str, find_relations_in_str_with, find_relations_with, Excerpt, Hash, Options, Relation, Relations, Report, Warning,
+    FORMAT_VERSION,
+};
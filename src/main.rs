@@ -9,8 +9,7 @@ use std::fs::File;
 use std::io::{self, BufRead, Write};
 use std::path::{Path, PathBuf};
 
-mod parse;
-mod sdoc;
+use sdoc_rs_relations::{sdoc, Columns, Keyword};
 
 #[derive(Debug, Parser)]
 #[command(version, about, long_about = None)]
@@ -26,6 +25,27 @@ struct Args {
     /// Output file (use '-' or omit for output to stdout)
     #[arg(short = 'o', long = "output", value_name = "FILE", default_value = "-")]
     output: PathBuf,
+    /// Additional marker keyword to look for, optionally with the role it gives its relations (repeatable)
+    #[arg(short = 'm', long = "marker", value_name = "KEYWORD[=ROLE]")]
+    markers: Vec<Keyword>,
+    /// Treat each path as a crate directory, following `mod` declarations from its crate roots and reporting unreachable files as orphans
+    #[arg(long = "crate")]
+    crates: bool,
+    /// Also emit the relations on an impl, trait or module on each item inside it, marked as inherited
+    #[arg(long = "inherit")]
+    inherit: bool,
+    /// Unit to count columns in: chars, bytes or utf16
+    #[arg(long = "columns", value_name = "UNIT", default_value = "chars")]
+    columns: Columns,
+    /// Embed the source text of each span in its relation, keeping the first LINES lines (and any signature) of longer spans
+    #[arg(long = "excerpt", value_name = "LINES", num_args = 0..=1, require_equals = true, default_missing_value = "20")]
+    excerpt: Option<usize>,
+    /// Maximum size of each excerpt, in bytes
+    #[arg(long = "excerpt-bytes", value_name = "BYTES", default_value = "4096", requires = "excerpt")]
+    excerpt_bytes: usize,
+    /// Print the JSON Schema of the output format and exit
+    #[arg(long = "print-schema")]
+    print_schema: bool,
     /// List of files and directories to search for Rust files
     #[arg(value_name = "PATHS")]
     paths: Vec<PathBuf>,
@@ -51,20 +71,49 @@ fn main() -> Result<()> {
     // Parse command-line arguments
     let args = Args::parse();
 
+    if args.print_schema {
+        let mut writer = writer_for(args.output.as_os_str())?;
+        serde_json::to_writer_pretty(&mut writer, &sdoc::json_schema())?;
+        writer.write_all(b"\n")?;
+        return Ok(());
+    }
+
+    let mut options = sdoc::Options {
+        inherit: args.inherit,
+        columns: args.columns,
+        excerpt: args.excerpt.map(|lines| sdoc::Excerpt {
+            lines,
+            bytes: args.excerpt_bytes,
+        }),
+        ..sdoc::Options::default()
+    };
+    for keyword in args.markers {
+        options.register(keyword);
+    }
+
     // Find Rust source files and process them
-    let mut files = vec![];
+    let mut relationships = vec![];
     for path in &args.paths {
-        files.extend(find_rust_files(path)?);
+        if args.crates {
+            relationships.extend(sdoc::find_relations_in_crate(path, &args.prefix, &options)?);
+        } else {
+            for file in find_rust_files(path)? {
+                relationships.push(sdoc::find_relations_with(&file, &args.prefix, &options)?);
+            }
+        }
     }
 
-    let mut relationships = vec![];
-    for file in files {
-        let relations = sdoc::find_relations(&file, &args.prefix)?;
-        relationships.push(relations);
+    for relations in &relationships {
+        for warning in &relations.warnings {
+            let start = warning.span.start;
+            eprintln!("warning: {}:{}:{}: {}", relations.file.display(), start.line, start.column, warning.message);
+        }
     }
 
+    let report = sdoc::Report::new(relationships);
+
     let mut writer = writer_for(args.output.as_os_str())?;
-    serde_json::to_writer_pretty(&mut writer, &relationships)?;
+    serde_json::to_writer_pretty(&mut writer, &report)?;
     writer.write_all(b"\n")?;
 
     Ok(())
@@ -0,0 +1,136 @@
+use std::{fmt, ops};
+
+use syn::punctuated::Punctuated;
+use syn::{Expr, ExprLit, Lit, Meta, Token};
+
+/// A [configuration predicate](https://doc.rust-lang.org/reference/conditional-compilation.html),
+/// as found in `#[cfg(...)]` and `#[cfg_attr(...)]` attributes.
+///
+/// Predicates are normalized as they are built, so that equivalent predicates print the same:
+/// nested `all` and `any` are flattened, their operands are sorted and deduplicated, single
+/// operands are unwrapped, and double negations are removed.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Cfg {
+    /// A configuration option, such as `unix` or `test`.
+    Option(String),
+    /// A configuration option with a value, such as `feature = "x"`.
+    KeyValue(String, String),
+    All(Vec<Cfg>),
+    Any(Vec<Cfg>),
+    Not(Box<Cfg>),
+}
+
+impl Cfg {
+    /// Parse a predicate, or `None` if it is not well-formed.
+    pub fn from_meta(meta: &Meta) -> Option<Cfg> {
+        match meta {
+            Meta::Path(path) => Some(Cfg::Option(path_string(path)?)),
+            Meta::NameValue(name_value) => match &name_value.value {
+                Expr::Lit(ExprLit {
+                    lit: Lit::Str(value), ..
+                }) => Some(Cfg::KeyValue(path_string(&name_value.path)?, value.value())),
+                _ => None,
+            },
+            Meta::List(list) => {
+                let nested = list.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated).ok()?;
+                let mut operands = nested.iter().map(Cfg::from_meta).collect::<Option<Vec<_>>>()?;
+                match path_string(&list.path)?.as_str() {
+                    "all" => Some(Cfg::all(operands)),
+                    "any" => Some(Cfg::any(operands)),
+                    "not" if operands.len() == 1 => Some(!operands.pop()?),
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    /// All of the predicates must hold.
+    pub fn all(operands: impl IntoIterator<Item = Cfg>) -> Cfg {
+        Cfg::combine(operands, Cfg::All, |cfg| match cfg {
+            Cfg::All(operands) => Ok(operands),
+            cfg => Err(cfg),
+        })
+    }
+
+    /// Any of the predicates must hold.
+    pub fn any(operands: impl IntoIterator<Item = Cfg>) -> Cfg {
+        Cfg::combine(operands, Cfg::Any, |cfg| match cfg {
+            Cfg::Any(operands) => Ok(operands),
+            cfg => Err(cfg),
+        })
+    }
+
+    fn combine(operands: impl IntoIterator<Item = Cfg>, wrap: fn(Vec<Cfg>) -> Cfg, unwrap: fn(Cfg) -> Result<Vec<Cfg>, Cfg>) -> Cfg {
+        let mut flattened = vec![];
+        for operand in operands {
+            match unwrap(operand) {
+                Ok(nested) => flattened.extend(nested),
+                Err(operand) => flattened.push(operand),
+            }
+        }
+        flattened.sort();
+        flattened.dedup();
+        if flattened.len() == 1 {
+            flattened.pop().expect("there is exactly one operand")
+        } else {
+            wrap(flattened)
+        }
+    }
+}
+
+/// The predicate must not hold.
+impl ops::Not for Cfg {
+    type Output = Cfg;
+
+    fn not(self) -> Cfg {
+        match self {
+            Cfg::Not(operand) => *operand,
+            operand => Cfg::Not(Box::new(operand)),
+        }
+    }
+}
+
+impl fmt::Display for Cfg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |f: &mut fmt::Formatter<'_>, name: &str, operands: &[Cfg]| {
+            write!(f, "{name}(")?;
+            for (index, operand) in operands.iter().enumerate() {
+                if index > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{operand}")?;
+            }
+            write!(f, ")")
+        };
+        match self {
+            Cfg::Option(name) => write!(f, "{name}"),
+            Cfg::KeyValue(name, value) => write!(f, "{name} = {value:?}"),
+            Cfg::All(operands) => list(f, "all", operands),
+            Cfg::Any(operands) => list(f, "any", operands),
+            Cfg::Not(operand) => write!(f, "not({operand})"),
+        }
+    }
+}
+
+fn path_string(path: &syn::Path) -> Option<String> {
+    path.get_ident().map(ToString::to_string)
+}
+
+#[test]
+fn test_cfg_normalized() {
+    let predicate = |src: &str| {
+        let meta: Meta = syn::parse_str(src).expect("a predicate");
+        Cfg::from_meta(&meta).map(|cfg| cfg.to_string())
+    };
+    assert_eq!(predicate("unix").as_deref(), Some("unix"));
+    assert_eq!(predicate(r#"feature = "x""#).as_deref(), Some(r#"feature = "x""#));
+    assert_eq!(
+        predicate(r#"all(unix, all(feature = "b", feature = "a"), unix)"#).as_deref(),
+        Some(r#"all(unix, feature = "a", feature = "b")"#)
+    );
+    assert_eq!(predicate("any(test)").as_deref(), Some("test"));
+    assert_eq!(predicate("not(not(test))").as_deref(), Some("test"));
+    assert_eq!(predicate("not(a, b)"), None);
+    assert_eq!(predicate("maybe(a)"), None);
+    assert_eq!(Cfg::all([Cfg::Option("b".into()), Cfg::any([Cfg::Option("a".into())])]).to_string(), "all(a, b)");
+}
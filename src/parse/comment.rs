@@ -0,0 +1,241 @@
+use crate::parse::lines::Lines;
+use crate::parse::Span;
+
+/// An ordinary (non-doc) `//` or `/* */` comment. These are thrown away by `syn`, so they are
+/// found by lexing the source text directly.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Comment<'a> {
+    /// The text between the comment delimiters.
+    pub text: &'a str,
+    /// The span of the whole comment, including its delimiters.
+    pub span: Span,
+    /// The byte offsets of the whole comment in the source, including its delimiters.
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Find all the ordinary comments in Rust source code, skipping over doc comments and any
+/// comment-like text inside string, raw string and character literals.
+pub fn comments<'a>(src: &'a str, lines: &Lines) -> Vec<Comment<'a>> {
+    let bytes = src.as_bytes();
+    let mut comments = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                let end = src[i..].find('\n').map_or(src.len(), |newline| i + newline);
+                let text = &src[i + 2..end];
+                let text = text.strip_suffix('\r').unwrap_or(text);
+                let doc = (text.starts_with('/') && !text.starts_with("//")) || text.starts_with('!');
+                if !doc {
+                    comments.push(comment(src, lines, i, end, text));
+                }
+                i = end;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                let end = block_comment_end(bytes, i);
+                let text = &src[i + 2..end.saturating_sub(2).max(i + 2)];
+                let doc = (text.starts_with('*') && !text.starts_with("**") && text != "*") || text.starts_with('!');
+                if !doc {
+                    comments.push(comment(src, lines, i, end, text));
+                }
+                i = end;
+            }
+            b'"' => i = string_end(bytes, i + 1),
+            b'\'' => i = char_end(bytes, i),
+            b'r' | b'b' | b'c' if i == 0 || !is_ident_byte(bytes[i - 1]) => i = prefixed_literal_end(bytes, i),
+            byte if is_ident_byte(byte) => {
+                // Skip the rest of the identifier, so that a trailing `r`, `b` or `c` is not a literal prefix
+                while i < bytes.len() && is_ident_byte(bytes[i]) {
+                    i += 1;
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    comments
+}
+
+/// Is there nothing but whitespace and other comments between two byte offsets, and no blank
+/// lines? That is, does a comment at `start` lead directly into the code at `end`?
+pub fn is_adjacent(src: &str, comments: &[Comment], start: usize, end: usize) -> bool {
+    let bytes = src.as_bytes();
+    let mut i = start;
+    let mut first = true; // the rest of the line the comment ends on
+    let mut content = false;
+    while i < end {
+        if let Ok(index) = comments.binary_search_by_key(&i, |comment| comment.start) {
+            i = comments[index].end;
+            content = true;
+            continue;
+        }
+        match bytes[i] {
+            b'\n' => {
+                if !first && !content {
+                    return false;
+                }
+                first = false;
+                content = false;
+            }
+            byte if byte.is_ascii_whitespace() => {}
+            _ => return false,
+        }
+        i += 1;
+    }
+    true
+}
+
+fn comment<'a>(src: &'a str, lines: &Lines, start: usize, end: usize, text: &'a str) -> Comment<'a> {
+    debug_assert!(src.is_char_boundary(start) && src.is_char_boundary(end));
+    Comment {
+        text,
+        span: Span {
+            start: lines.position(start),
+            end: lines.position(end),
+        },
+        start,
+        end,
+    }
+}
+
+fn is_ident_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_' || !byte.is_ascii()
+}
+
+/// Block comments nest, so `/* /* */ */` is a single comment.
+fn block_comment_end(bytes: &[u8], start: usize) -> usize {
+    let mut depth = 0;
+    let mut i = start;
+    while i < bytes.len() {
+        if bytes[i] == b'/' && bytes.get(i + 1) == Some(&b'*') {
+            depth += 1;
+            i += 2;
+        } else if bytes[i] == b'*' && bytes.get(i + 1) == Some(&b'/') {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return i;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    bytes.len()
+}
+
+/// The offset just past the closing quote of a string literal whose contents start at `i`.
+fn string_end(bytes: &[u8], mut i: usize) -> usize {
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'"' => return i + 1,
+            _ => i += 1,
+        }
+    }
+    bytes.len()
+}
+
+/// A `'` starts either a character literal or a lifetime (or loop label).
+fn char_end(bytes: &[u8], start: usize) -> usize {
+    let i = start + 1;
+    if bytes.get(i) == Some(&b'\\') {
+        // An escaped character literal such as '\n', '\'' or '\u{1F600}'
+        let mut i = i + 2;
+        while i < bytes.len() && bytes[i] != b'\'' {
+            i += 1;
+        }
+        return (i + 1).min(bytes.len());
+    }
+    // The character may be several bytes long
+    let width = bytes.get(i).map_or(1, |&byte| utf8_width(byte));
+    if bytes.get(i + width) == Some(&b'\'') {
+        i + width + 1
+    } else {
+        i // a lifetime, whose name is then skipped as an identifier
+    }
+}
+
+fn utf8_width(byte: u8) -> usize {
+    match byte {
+        0xF0.. => 4,
+        0xE0.. => 3,
+        0xC0.. => 2,
+        _ => 1,
+    }
+}
+
+/// Handle the literals with a prefix: `b"..."`, `c"..."`, `b'.'` and the raw strings
+/// `r"..."`, `br#"..."#` and `cr##"..."##`. Anything else is an identifier.
+fn prefixed_literal_end(bytes: &[u8], start: usize) -> usize {
+    let mut i = start;
+    if matches!(bytes[i], b'b' | b'c') {
+        match bytes.get(i + 1) {
+            Some(b'"') => return string_end(bytes, i + 2),
+            Some(b'\'') if bytes[i] == b'b' => return char_end(bytes, i + 1),
+            Some(b'r') => i += 1,
+            _ => return start + 1,
+        }
+    }
+    // At the `r` of a raw string, or of an identifier (including a raw identifier like `r#type`)
+    let mut j = i + 1;
+    while bytes.get(j) == Some(&b'#') {
+        j += 1;
+    }
+    if bytes.get(j) != Some(&b'"') {
+        return start + 1;
+    }
+    let hashes = j - (i + 1);
+    let mut k = j + 1;
+    while k < bytes.len() {
+        if bytes[k] == b'"' && bytes[k + 1..].iter().take(hashes).filter(|&&byte| byte == b'#').count() == hashes {
+            return k + 1 + hashes;
+        }
+        k += 1;
+    }
+    bytes.len()
+}
+
+#[test]
+fn test_comments() {
+    let src = r####"//! Inner doc, not a comment
+/// Outer doc, not a comment
+//// Four slashes are a comment
+// Line @relation(REQ-1)
+fn main() {
+    let s = "not // a comment";
+    let r = r#"not /* a "comment" */"#;
+    let b = br"not // either";
+    let c = '"'; // after a char
+    let l: &'static str = "x"; /* block /* nested */ still */
+    /** block doc */ /*! inner block doc */ /***/ /**/
+    let é = 'é'; // Unicode
+}
+"####;
+    let lines = Lines::new(src);
+    let found: Vec<_> = comments(src, &lines).iter().map(|comment| (comment.text, comment.span.start.line.get(), comment.span.start.column)).collect();
+    assert_eq!(
+        found,
+        vec![
+            ("// Four slashes are a comment", 3, 0),
+            (" Line @relation(REQ-1)", 4, 0),
+            (" after a char", 9, 17),
+            (" block /* nested */ still ", 10, 31),
+            ("*", 11, 44),
+            ("", 11, 50),
+            (" Unicode", 12, 17),
+        ]
+    );
+}
+
+#[test]
+fn test_is_adjacent() {
+    let src = "// a\n// b\n\n// c\n  /* d */\n  fn x() {}\n";
+    let lines = Lines::new(src);
+    let found = comments(src, &lines);
+    let code = src.find("fn").unwrap();
+    assert!(!is_adjacent(src, &found, found[0].end, code));
+    assert!(!is_adjacent(src, &found, found[1].end, code));
+    assert!(is_adjacent(src, &found, found[2].end, code));
+    assert!(is_adjacent(src, &found, found[3].end, code));
+    assert!(!is_adjacent(src, &found, 0, code));
+}
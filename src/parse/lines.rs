@@ -0,0 +1,157 @@
+use crate::parse::{Columns, LineColumn, Span};
+
+use std::num::NonZeroUsize;
+
+/// An index of where each line starts in a source file, used to build spans that do not
+/// come from a `syn` node, such as a single line or the whole file.
+///
+/// As for `rustc` and `syn`, the first line starts after any byte order mark, so that columns
+/// agree with theirs, while byte offsets are still those in the file.
+#[derive(Clone, Debug)]
+pub struct Lines<'a> {
+    src: &'a str,
+    starts: Vec<usize>,
+}
+
+impl<'a> Lines<'a> {
+    pub fn new(src: &'a str) -> Self {
+        let bom = if src.starts_with('\u{feff}') { '\u{feff}'.len_utf8() } else { 0 };
+        let starts = std::iter::once(bom)
+            .chain(src.match_indices('\n').map(|(offset, _)| offset + 1))
+            .collect();
+        Lines { src, starts }
+    }
+
+    /// The line and (character) column of a byte offset in the source. As for positions from
+    /// `syn`, the offset is left at 0 until it is filled in by [`Lines::convert`].
+    pub fn position(&self, offset: usize) -> LineColumn {
+        let offset = offset.max(self.starts[0]);
+        let index = self.starts.partition_point(|&start| start <= offset) - 1;
+        LineColumn {
+            line: NonZeroUsize::new(index + 1).expect("line numbers are 1-based"),
+            column: self.src[self.starts[index]..offset].chars().count(),
+            offset: 0,
+        }
+    }
+
+    /// Fill in the byte offset of a line and (character) column, and count the column in the
+    /// given units instead.
+    pub fn convert(&self, position: LineColumn, columns: Columns) -> LineColumn {
+        let offset = self.offset(position);
+        let start = self.starts[position.line.get() - 1];
+        let column = match columns {
+            Columns::Chars => position.column,
+            Columns::Bytes => offset - start,
+            Columns::Utf16 => self.src[start..offset].encode_utf16().count(),
+        };
+        LineColumn { column, offset, ..position }
+    }
+
+    /// The byte offset in the source of a line and (character) column.
+    pub fn offset(&self, position: LineColumn) -> usize {
+        let start = self.starts[position.line.get() - 1];
+        self.src[start..]
+            .char_indices()
+            .nth(position.column)
+            .map_or(self.src.len(), |(offset, _)| start + offset)
+    }
+
+    /// Convert both ends of a span, as for [`Lines::convert`].
+    pub fn convert_span(&self, span: Span, columns: Columns) -> Span {
+        Span {
+            start: self.convert(span.start, columns),
+            end: self.convert(span.end, columns),
+        }
+    }
+
+    /// The text of the given 1-based line, without its line ending.
+    pub fn line(&self, line: NonZeroUsize) -> Option<&'a str> {
+        let start = *self.starts.get(line.get() - 1)?;
+        let end = self.starts.get(line.get()).map_or(self.src.len(), |&next| next - 1);
+        let text = &self.src[start..end];
+        Some(text.strip_suffix('\r').unwrap_or(text))
+    }
+
+    /// The span of the given 1-based line, from its first to its last character.
+    pub fn line_span(&self, line: NonZeroUsize) -> Option<Span> {
+        let text = self.line(line)?;
+        Some(Span {
+            start: LineColumn { line, column: 0, offset: 0 },
+            end: LineColumn {
+                line,
+                column: text.chars().count(),
+                offset: 0,
+            },
+        })
+    }
+
+    /// The span of the whole file, from its first character to the end of its last non-empty line.
+    pub fn file_span(&self) -> Span {
+        let last = (1..=self.starts.len())
+            .rev()
+            .filter_map(NonZeroUsize::new)
+            .find(|&line| self.line(line).is_some_and(|text| !text.is_empty()))
+            .unwrap_or(NonZeroUsize::MIN);
+        let end = self.line_span(last).expect("the last line should exist").end;
+        Span {
+            start: LineColumn {
+                line: NonZeroUsize::MIN,
+                column: 0,
+                offset: 0,
+            },
+            end,
+        }
+    }
+}
+
+#[test]
+fn test_lines() {
+    let lines = Lines::new("fn a() {}\r\n\n  fn bé() {}\n");
+    let line = |n| NonZeroUsize::new(n).unwrap();
+    assert_eq!(lines.line(line(1)), Some("fn a() {}"));
+    assert_eq!(lines.line(line(2)), Some(""));
+    assert_eq!(lines.line(line(3)), Some("  fn bé() {}"));
+    assert_eq!(lines.line(line(4)), Some(""));
+    assert_eq!(lines.line(line(5)), None);
+    assert_eq!(lines.line_span(line(3)).unwrap().end.column, 12);
+    let position = |n, column, offset| LineColumn { line: line(n), column, offset };
+    assert_eq!(lines.position(17), position(3, 5, 0));
+    assert_eq!(lines.offset(position(3, 5, 0)), 17);
+    assert_eq!(lines.position(20), position(3, 7, 0));
+    assert_eq!(lines.offset(position(3, 7, 0)), 20);
+    let file = lines.file_span();
+    assert_eq!(file.start, position(1, 0, 0));
+    assert_eq!(file.end, position(3, 12, 0));
+    let file = lines.convert_span(file, Columns::Chars);
+    assert_eq!(file.start, position(1, 0, 0));
+    assert_eq!(file.end, position(3, 12, 25));
+}
+
+#[test]
+fn test_lines_convert() {
+    // `é` is 2 bytes and 1 UTF-16 code unit, and `😀` is 4 bytes and 2 UTF-16 code units
+    let lines = Lines::new("ab\n// é😀 x\n");
+    let x = LineColumn {
+        line: NonZeroUsize::new(2).unwrap(),
+        column: 6,
+        offset: 0,
+    };
+    assert_eq!(lines.convert(x, Columns::Chars), LineColumn { offset: 13, ..x });
+    assert_eq!(lines.convert(x, Columns::Bytes), LineColumn { column: 10, offset: 13, ..x });
+    assert_eq!(lines.convert(x, Columns::Utf16), LineColumn { column: 7, offset: 13, ..x });
+}
+
+#[test]
+fn test_lines_bom() {
+    let lines = Lines::new("\u{feff}ab\ncd\n");
+    let position = |column, offset| LineColumn {
+        line: NonZeroUsize::MIN,
+        column,
+        offset,
+    };
+    assert_eq!(lines.line(NonZeroUsize::MIN), Some("ab"));
+    assert_eq!(lines.position(4), position(1, 0));
+    assert_eq!(lines.offset(lines.position(4)), 4);
+    assert_eq!(lines.convert(lines.file_span().start, Columns::Chars), position(0, 3));
+    assert_eq!(lines.convert(position(1, 0), Columns::Bytes), position(1, 4));
+}
@@ -1,23 +1,69 @@
+pub mod cfg;
+pub mod comment;
+pub mod lines;
+pub mod module;
 mod relation;
+pub mod tokens;
 pub mod tree;
 
-use anyhow::{bail, Result};
-use serde::Serialize;
+use anyhow::{anyhow, bail, Result};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 
-use crate::parse::relation::{is_opening, next, relation};
+use crate::parse::relation::{is_legacy, legacy, next, opening_keyword, relation, skip, RELATION};
 use std::collections::BTreeMap;
+use syn::punctuated::Punctuated;
+use syn::{Expr, ExprAssign, ExprLit, ExprPath, Lit, Token};
 use std::num::NonZeroUsize;
+use std::str::FromStr;
 
 /// Line and column numbers are 1-based and 0-based, respectively,
 /// consistent with the definition in [`proc_macro2::LineColumn`](https://docs.rs/proc-macro2/latest/proc_macro2/struct.LineColumn.html).
 /// However, we specify `line` as a `NonZeroUsize` to make this more explicit.
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
 pub struct LineColumn {
     /// The 1-indexed line in the source file on which the span starts or ends (inclusive).
     pub line: NonZeroUsize,
-    /// The 0-indexed column (in UTF-8 characters) in the source file on which the span starts or ends (inclusive).
+    /// The 0-indexed column in the source file on which the span starts or ends (inclusive),
+    /// counted in the units given by `columns` (UTF-8 characters by default).
     pub column: usize,
+    /// The 0-indexed byte offset in the source file of the start or end of the span.
+    #[serde(default)]
+    pub offset: usize,
+}
+
+/// The unit in which [`LineColumn::column`] is counted.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Columns {
+    /// Unicode characters (scalar values), as `proc_macro2` and `rustc` count them.
+    #[default]
+    Chars,
+    /// UTF-8 bytes.
+    Bytes,
+    /// UTF-16 code units, as the Language Server Protocol and JavaScript count them.
+    Utf16,
+}
+
+impl Columns {
+    pub fn is_chars(&self) -> bool {
+        *self == Columns::Chars
+    }
+}
+
+/// Parse `chars`, `bytes` or `utf16`.
+impl FromStr for Columns {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "chars" => Ok(Columns::Chars),
+            "bytes" => Ok(Columns::Bytes),
+            "utf16" => Ok(Columns::Utf16),
+            _ => Err(anyhow!("invalid column unit (expected chars, bytes or utf16): {s}")),
+        }
+    }
 }
 
 /// Copied from [`proc_macro2::LineColumn`](https://docs.rs/proc-macro2/latest/proc_macro2/struct.LineColumn.html).
@@ -42,12 +88,14 @@ impl From<proc_macro2::LineColumn> for LineColumn {
             line: NonZeroUsize::new(lc.line)
                 .expect("proc_macro2::LineColumn line numbers should be non-zero"),
             column: lc.column,
+            offset: 0, // `proc_macro2` does not know, so filled in from the source
         }
     }
 }
 
 /// Copied from [`proc_macro2::Span.html`](https://docs.rs/proc-macro2/latest/proc_macro2/struct.Span.html).
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, JsonSchema)]
+#[schemars(description = "A region of a source file, from `start` to `end`.")]
 pub struct Span {
     pub start: LineColumn,
     pub end: LineColumn,
@@ -63,43 +111,166 @@ impl From<proc_macro2::Span> for Span {
     }
 }
 
+/// The path of the structured attribute, `#[sdoc::relation(...)]`, as an alternative to markers.
+pub const ATTRIBUTE: &str = "sdoc::relation";
+
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
 pub struct Relation {
     pub identifier: String,
     pub attributes: BTreeMap<String, String>,
+    /// The role given to relations by the keyword of the marker, if any.
+    pub role: Option<String>,
+    /// The byte offset of the marker in the doc string it was found in.
+    pub offset: usize,
+    /// The length in bytes of the marker in the doc string.
+    pub length: usize,
+    /// Is this one end of a legacy `@sdoc[REQ]` ... `@sdoc[/REQ]` block?
+    pub block: Option<Block>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub enum Block {
+    Start,
+    End,
 }
 
-pub fn relations_from_doc(mut input: &str) -> Result<Vec<Relation>> {
+/// A marker keyword, such as `@relation` or `@verifies`, and the role it gives to the
+/// relations found with it.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Keyword {
+    pub keyword: String,
+    pub role: Option<String>,
+}
+
+impl Default for Keyword {
+    fn default() -> Self {
+        Keyword {
+            keyword: RELATION.to_string(),
+            role: None,
+        }
+    }
+}
+
+/// Parse `KEYWORD` or `KEYWORD=ROLE`, where the leading `@` of the keyword is optional.
+impl FromStr for Keyword {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (keyword, role) = match s.split_once('=') {
+            Some((keyword, role)) => (keyword, Some(role)),
+            None => (s, None),
+        };
+        let keyword = keyword.strip_prefix('@').unwrap_or(keyword);
+        if keyword.is_empty() || !keyword.chars().all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | ':')) {
+            return Err(anyhow!("invalid marker keyword: {s}"));
+        }
+        if role.is_some_and(str::is_empty) {
+            return Err(anyhow!("empty role for marker keyword: {s}"));
+        }
+        Ok(Keyword {
+            keyword: format!("@{keyword}"),
+            role: role.map(str::to_string),
+        })
+    }
+}
+
+/// Find the relations in a doc string using the default `@relation` keyword.
+pub fn relations_from_doc(doc: &str) -> Result<Vec<Relation>> {
+    relations_from_doc_with(doc, &[Keyword::default()])
+}
+
+/// Find the relations in a doc string using the given marker keywords.
+pub fn relations_from_doc_with(doc: &str, keywords: &[Keyword]) -> Result<Vec<Relation>> {
     let mut relations = Vec::new();
+    let mut input = doc;
 
     // Find the next candidate tag, but it might only be a partial match
-    while let Ok((next, _)) = next(input) {
+    while let Ok((next, _)) = next(input, keywords) {
         input = next;
+        let offset = doc.len() - input.len();
 
         // Make sure it is a complete, proper opening tag
-        if is_opening(input) {
+        if let Some(keyword) = opening_keyword(input, keywords) {
             // It is an opening tag, so let's try to parse it
-            match relation(input) {
+            match relation(input, keyword) {
                 Ok((remaining, relation)) => {
-                    relations.push(relation);
+                    let length = input.len() - remaining.len();
+                    relations.extend(relation.into_iter().map(|relation| Relation { offset, length, ..relation }));
                     input = remaining;
                 }
-                Err(_) => {
-                    const LENGTH: usize = 32;
-                    let truncated = if input.len() > LENGTH {
-                        format!("{}...", &input[..LENGTH])
-                    } else {
-                        input.to_string()
-                    };
-                    bail!("malformed: {truncated}"); // note a likely error
+                Err(_) => malformed(input)?,
+            }
+        } else if is_legacy(input) {
+            match legacy(input) {
+                Ok((remaining, relation)) => {
+                    let length = input.len() - remaining.len();
+                    relations.push(Relation { offset, length, ..relation });
+                    input = remaining;
                 }
+                Err(_) => malformed(input)?,
             }
+        } else {
+            input = skip(input);
         }
     }
 
     Ok(relations)
 }
 
+/// Find the relations in the arguments of a structured `#[sdoc::relation("REQ-1", ...)]`
+/// attribute: one or more string identifiers, then any `key = "value"` attributes, of which
+/// `role` gives the role of the relations.
+pub fn relations_from_attribute(list: &syn::MetaList) -> Result<Vec<Relation>> {
+    let malformed = || anyhow!("malformed: #[{}({})]", ATTRIBUTE, list.tokens);
+    let arguments = list
+        .parse_args_with(Punctuated::<Expr, Token![,]>::parse_terminated)
+        .map_err(|_| malformed())?;
+    let string = |expr: &Expr| match expr {
+        Expr::Lit(ExprLit { lit: Lit::Str(value), .. }) => Some(value.value()),
+        _ => None,
+    };
+    let mut identifiers = vec![];
+    let mut attributes = BTreeMap::new();
+    for argument in &arguments {
+        match argument {
+            Expr::Assign(ExprAssign { left, right, .. }) if !identifiers.is_empty() => {
+                let Expr::Path(ExprPath { path, .. }) = left.as_ref() else {
+                    return Err(malformed());
+                };
+                let key = path.get_ident().ok_or_else(malformed)?.to_string();
+                attributes.insert(key, string(right).ok_or_else(malformed)?);
+            }
+            argument if attributes.is_empty() => identifiers.push(string(argument).ok_or_else(malformed)?),
+            _ => return Err(malformed()),
+        }
+    }
+    if identifiers.is_empty() {
+        return Err(malformed());
+    }
+    let role = attributes.remove("role");
+    Ok(identifiers
+        .into_iter()
+        .map(|identifier| Relation {
+            identifier,
+            attributes: attributes.clone(),
+            role: role.clone(),
+            offset: 0,
+            length: 0,
+            block: None,
+        })
+        .collect())
+}
+
+fn malformed(input: &str) -> Result<()> {
+    const LENGTH: usize = 32;
+    let truncated = if input.len() > LENGTH {
+        format!("{}...", &input[..input.floor_char_boundary(LENGTH)])
+    } else {
+        input.to_string()
+    };
+    bail!("malformed: {truncated}"); // note a likely error
+}
+
 #[test]
 fn test_relations_from_doc() -> Result<()> {
     let relations = relations_from_doc(
@@ -112,6 +283,9 @@ fn test_relations_from_doc() -> Result<()> {
     assert_eq!(relations[1].attributes.len(), 1);
     assert_eq!(relations[0].attributes["attr1"], "val1");
     assert_eq!(relations[1].attributes["attr2"], "val2");
+    assert_eq!(relations[0].offset, 7);
+    assert_eq!(relations[1].offset, 44);
+    assert_eq!(relations[0].length, "@relation(ident1, attr1=val1)".len());
     Ok(())
 }
 
@@ -123,3 +297,99 @@ fn test_relations_from_doc_braces_value_ok() -> Result<()> {
     assert_eq!(relations[0].attributes["attr"], "va{}ue");
     Ok(())
 }
+
+#[test]
+fn test_relations_from_doc_multiple_identifiers() -> Result<()> {
+    let relations = relations_from_doc("prefix @relation(REQ-1, REQ-2 ,REQ-3, scope=function, attr=val) suffix")?;
+    assert_eq!(relations.len(), 3);
+    assert_eq!(relations[0].identifier, "REQ-1");
+    assert_eq!(relations[1].identifier, "REQ-2");
+    assert_eq!(relations[2].identifier, "REQ-3");
+    for relation in &relations {
+        assert_eq!(relation.attributes.len(), 2);
+        assert_eq!(relation.attributes["scope"], "function");
+        assert_eq!(relation.attributes["attr"], "val");
+    }
+    Ok(())
+}
+
+#[test]
+fn test_relations_from_doc_identifier_after_attribute_malformed() {
+    assert!(relations_from_doc("prefix @relation(REQ-1, attr=val, REQ-2) suffix").is_err());
+}
+
+#[test]
+fn test_relations_from_doc_partial_keywords_skipped() -> Result<()> {
+    let relations = relations_from_doc("see @relationship and @sdoc for details, then @relation(REQ-1)")?;
+    assert_eq!(relations.len(), 1);
+    assert_eq!(relations[0].identifier, "REQ-1");
+    Ok(())
+}
+
+#[test]
+fn test_relations_from_doc_legacy_blocks() -> Result<()> {
+    let relations = relations_from_doc("@sdoc[REQ-001] and @relation(REQ-2) until @sdoc[/REQ-001]")?;
+    assert_eq!(relations.len(), 3);
+    assert_eq!(relations[0].identifier, "REQ-001");
+    assert_eq!(relations[0].block, Some(Block::Start));
+    assert_eq!(relations[1].identifier, "REQ-2");
+    assert_eq!(relations[1].block, None);
+    assert_eq!(relations[2].identifier, "REQ-001");
+    assert_eq!(relations[2].block, Some(Block::End));
+    assert_eq!(relations[2].offset, 42);
+    assert!(relations_from_doc("@sdoc[REQ-001").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_relations_from_doc_quoted_values() -> Result<()> {
+    let relations = relations_from_doc(
+        r#"@relation("REQ 1", REQ-2, note="covers the (a), (b) cases", title="Größe \"μ\" \u{1F600}\n", bare=va"lue)"#,
+    )?;
+    assert_eq!(relations.len(), 2);
+    assert_eq!(relations[0].identifier, "REQ 1");
+    assert_eq!(relations[1].identifier, "REQ-2");
+    assert_eq!(relations[0].attributes["note"], "covers the (a), (b) cases");
+    assert_eq!(relations[0].attributes["title"], "Größe \"μ\" \u{1F600}\n");
+    assert_eq!(relations[0].attributes["bare"], "va\"lue");
+    // An unterminated quote is a bare token, as it was before quoting was supported
+    assert_eq!(relations_from_doc(r#"@relation(REQ-1, note="unterminated)"#)?[0].attributes["note"], "\"unterminated");
+    assert!(relations_from_doc(r#"@relation(REQ-1, note="bad \q escape")"#).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_relations_from_doc_with_keywords() -> Result<()> {
+    let keywords = ["@relation".parse()?, "@implements=implements".parse()?, "verifies=verifies".parse()?, "@rel=short".parse()?];
+    let relations = relations_from_doc_with("@implements(REQ-1) @verifies(REQ-2, k=v) @relation(REQ-3) @rel(REQ-4)", &keywords)?;
+    let found: Vec<_> = relations.iter().map(|relation| (relation.identifier.as_str(), relation.role.as_deref())).collect();
+    assert_eq!(
+        found,
+        vec![("REQ-1", Some("implements")), ("REQ-2", Some("verifies")), ("REQ-3", None), ("REQ-4", Some("short"))]
+    );
+    assert!("@".parse::<Keyword>().is_err());
+    assert!("@has space=role".parse::<Keyword>().is_err());
+    assert!("@empty=".parse::<Keyword>().is_err());
+    Ok(())
+}
+
+#[test]
+fn test_relations_from_attribute() -> Result<()> {
+    let attribute = |arguments: &str| -> Result<Vec<Relation>> {
+        let list: syn::MetaList = syn::parse_str(&format!("sdoc::relation({arguments})"))?;
+        relations_from_attribute(&list)
+    };
+    let relations = attribute(r#""REQ-1", "REQ 2", role = "implements", scope = "function""#)?;
+    assert_eq!(relations.len(), 2);
+    assert_eq!(relations[0].identifier, "REQ-1");
+    assert_eq!(relations[1].identifier, "REQ 2");
+    assert_eq!(relations[0].role.as_deref(), Some("implements"));
+    assert_eq!(relations[1].attributes["scope"], "function");
+    assert!(!relations[0].attributes.contains_key("role"));
+    assert!(attribute(r#"REQ_1"#).is_err());
+    assert!(attribute(r#"role = "implements""#).is_err());
+    assert!(attribute(r#""REQ-1", role = "implements", "REQ-2""#).is_err());
+    assert!(attribute(r#""REQ-1", role = 1"#).is_err());
+    assert!(attribute("").is_err());
+    Ok(())
+}
@@ -0,0 +1,197 @@
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use syn::spanned::Spanned;
+use syn::{Attribute, Expr, ExprLit, Item, Lit, Meta};
+use walkdir::WalkDir;
+
+use std::collections::BTreeSet;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::parse::Span;
+
+/// A source file reachable from a crate root by following `mod` declarations.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Module {
+    pub file: PathBuf,
+    /// The module path of the file, such as `crate::storage`.
+    pub path: String,
+    /// Declarations in the file of modules whose file could not be found.
+    pub missing: Vec<(Span, String)>,
+}
+
+/// The source files of a crate.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Crate {
+    /// The files reachable from the crate roots, in the order they were reached.
+    pub modules: Vec<Module>,
+    /// The Rust files in the crate's directory that are not reachable from any root.
+    pub orphans: Vec<PathBuf>,
+}
+
+/// Find the source files of the crate in the directory `root`, starting from its roots
+/// (`src/lib.rs`, `src/main.rs`, `build.rs`, the binaries, examples, tests and benchmarks in
+/// their conventional directories, and any other library, binary or build script paths given
+/// in `Cargo.toml`) and following `mod` declarations, including `#[path = "..."]`
+/// attributes and both the `foo.rs` and `foo/mod.rs` layouts.
+///
+/// Subdirectories containing a `Cargo.toml` are other crates, and are not searched for orphans.
+pub fn resolve(root: &Path) -> Result<Crate> {
+    if !root.is_dir() {
+        return Err(anyhow!("not a crate directory: {}", root.display()));
+    }
+    let mut resolver = Resolver::default();
+    for file in crate_roots(root)? {
+        resolver.file(&file, "crate".to_string(), true)?;
+    }
+    let mut orphans = vec![];
+    let walker = WalkDir::new(root)
+        .follow_root_links(true)
+        .follow_links(false)
+        .into_iter()
+        .filter_entry(|entry| entry.depth() == 0 || !(entry.file_type().is_dir() && (entry.file_name() == "target" || entry.path().join("Cargo.toml").exists())));
+    for entry in walker {
+        let path = entry?.into_path();
+        if path.is_file() && path.extension() == Some(OsStr::new("rs")) && !resolver.seen.contains(&fs::canonicalize(&path)?) {
+            orphans.push(path);
+        }
+    }
+    orphans.sort_unstable();
+    Ok(Crate {
+        modules: resolver.modules,
+        orphans,
+    })
+}
+
+/// The parts of a `Cargo.toml` that say where the crate roots are.
+#[derive(Debug, Default, Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    package: Package,
+    lib: Option<Target>,
+    #[serde(default)]
+    bin: Vec<Target>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Package {
+    /// The path of the build script, or `false` if there is none.
+    build: Option<toml::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Target {
+    path: Option<PathBuf>,
+}
+
+/// The crate roots that exist in the directory, as Cargo would find them: the build script,
+/// library and binaries given in `Cargo.toml`, if any, and those in the conventional places.
+fn crate_roots(root: &Path) -> Result<Vec<PathBuf>> {
+    let file = root.join("Cargo.toml");
+    let manifest: Manifest = match fs::read_to_string(&file) {
+        Ok(text) => toml::from_str(&text).with_context(|| format!("failed parsing: {}", file.display()))?,
+        Err(_) => Manifest::default(),
+    };
+    let lib = manifest.lib.and_then(|lib| lib.path).unwrap_or_else(|| "src/lib.rs".into());
+    let mut roots = vec![root.join(lib), root.join("src/main.rs")];
+    roots.extend(manifest.bin.into_iter().filter_map(|bin| bin.path).map(|path| root.join(path)));
+    for directory in ["src/bin", "examples", "tests", "benches"] {
+        let Ok(entries) = fs::read_dir(root.join(directory)) else {
+            continue;
+        };
+        let mut found: Vec<_> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .map(|path| match path.extension() {
+                Some(extension) if extension == "rs" => path,
+                _ => path.join("main.rs"),
+            })
+            .collect();
+        found.sort_unstable();
+        roots.extend(found);
+    }
+    match manifest.package.build {
+        Some(toml::Value::String(build)) => roots.push(root.join(build)),
+        Some(toml::Value::Boolean(false)) => {}
+        _ => roots.push(root.join("build.rs")),
+    }
+    roots.retain(|root| root.is_file());
+    Ok(roots)
+}
+
+#[derive(Default)]
+struct Resolver {
+    modules: Vec<Module>,
+    /// The canonical paths of the files reached so far.
+    seen: BTreeSet<PathBuf>,
+}
+
+impl Resolver {
+    /// Add a module file and follow its `mod` declarations. A "mod-rs" file (a crate root, a
+    /// `mod.rs`, or a file given by a `#[path]` attribute) owns the directory it is in, while
+    /// the submodules of any other `foo.rs` are in the directory `foo`.
+    fn file(&mut self, file: &Path, path: String, mod_rs: bool) -> Result<()> {
+        // Files may be reached by different paths, such as through `#[path = "../foo.rs"]`
+        if !self.seen.insert(fs::canonicalize(file).with_context(|| format!("failed to read source file: {}", file.display()))?) {
+            return Ok(());
+        }
+        let src = fs::read_to_string(file).with_context(|| format!("failed to read source file: {}", file.display()))?;
+        let ast = syn::parse_file(&src).with_context(|| format!("failed parsing: {}", file.display()))?;
+        let directory = file.parent().unwrap_or(Path::new(""));
+        let owned = match file.file_stem() {
+            Some(stem) if !mod_rs => directory.join(stem),
+            _ => directory.to_path_buf(),
+        };
+        let index = self.modules.len();
+        self.modules.push(Module {
+            file: file.to_path_buf(),
+            path: path.clone(),
+            missing: vec![],
+        });
+        let mut missing = vec![];
+        self.items(&ast.items, &path, directory, &owned, &mut missing)?;
+        self.modules[index].missing = missing;
+        Ok(())
+    }
+
+    /// Follow the `mod` declarations in a list of items. A `#[path]` attribute is relative to
+    /// `directory`, and files for modules without one are found in `owned`. These are the same
+    /// directory inside inline modules, but may differ at the top level of a file.
+    fn items(&mut self, items: &[Item], path: &str, directory: &Path, owned: &Path, missing: &mut Vec<(Span, String)>) -> Result<()> {
+        for item in items {
+            let Item::Mod(item) = item else {
+                continue;
+            };
+            let name = item.ident.to_string();
+            let name = name.strip_prefix("r#").unwrap_or(&name);
+            let child = format!("{path}::{name}");
+            let attribute = path_attribute(&item.attrs);
+            if let Some((_, items)) = &item.content {
+                let inline = owned.join(attribute.as_deref().unwrap_or(name));
+                self.items(items, &child, &inline, &inline, missing)?;
+                continue;
+            }
+            let candidates = match attribute {
+                Some(attribute) => vec![(directory.join(attribute), true)],
+                None => vec![(owned.join(format!("{name}.rs")), false), (owned.join(name).join("mod.rs"), true)],
+            };
+            match candidates.into_iter().find(|(file, _)| file.is_file()) {
+                Some((file, mod_rs)) => self.file(&file, child, mod_rs)?,
+                None => missing.push((item.span().into(), format!("file not found for module `{name}`"))),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The value of a `#[path = "..."]` attribute, if any.
+fn path_attribute(attributes: &[Attribute]) -> Option<String> {
+    attributes.iter().find_map(|attribute| match &attribute.meta {
+        Meta::NameValue(name_value) if name_value.path.is_ident("path") => match &name_value.value {
+            Expr::Lit(ExprLit { lit: Lit::Str(value), .. }) => Some(value.value()),
+            _ => None,
+        },
+        _ => None,
+    })
+}
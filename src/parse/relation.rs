@@ -1,25 +1,57 @@
-use crate::parse::Relation;
+use crate::parse::{Block, Keyword, Relation};
 
+use std::borrow::Cow;
 use std::collections::BTreeMap;
 
-use nom::bytes::complete::{tag, take_until, take_while, take_while1};
-use nom::character::complete::char;
-use nom::combinator::peek;
-use nom::multi::many0;
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take_while, take_while1, take_while_m_n};
+use nom::character::complete::{char, none_of};
+use nom::combinator::{map, map_opt, not, opt, peek, value};
+use nom::error::{Error, ErrorKind};
+use nom::multi::{fold_many0, many0};
+use nom::sequence::{delimited, preceded, terminated};
 use nom::{AsChar, IResult, Parser};
 
-const RELATION: &str = "@relation";
+/// The keyword of the standard StrictDoc marker.
+pub const RELATION: &str = "@relation";
 
-pub fn next(input: &str) -> IResult<&str, &str> {
-    take_until(RELATION)(input)
+/// The legacy StrictDoc block markers, `@sdoc[REQ]` ... `@sdoc[/REQ]`.
+const SDOC: &str = "@sdoc";
+
+/// Find the next candidate marker, which might only be a partial match.
+pub fn next<'a>(input: &'a str, keywords: &[Keyword]) -> IResult<&'a str, &'a str> {
+    let markers = keywords.iter().map(|keyword| keyword.keyword.as_str()).chain([SDOC]);
+    match markers.filter_map(|marker| input.find(marker)).min() {
+        Some(index) => Ok((&input[index..], &input[..index])),
+        None => Err(nom::Err::Error(Error::new(input, ErrorKind::TakeUntil))),
+    }
+}
+
+/// Skip past the start of a candidate marker that turned out not to be an opening.
+pub fn skip(input: &str) -> &str {
+    &input[1..]
 }
 
-pub fn is_opening(input: &str) -> bool {
-    peek(opening).parse(input).is_ok()
+/// The keyword of the complete, proper opening tag at the start of the input, if any. When
+/// several keywords match, such as `@rel` and `@relation`, the longest one wins.
+pub fn opening_keyword<'k>(input: &str, keywords: &'k [Keyword]) -> Option<&'k Keyword> {
+    keywords
+        .iter()
+        .filter(|keyword| peek(|input| opening(input, &keyword.keyword)).parse(input).is_ok())
+        .max_by_key(|keyword| keyword.keyword.len())
 }
 
-fn opening(input: &str) -> IResult<&str, ()> {
-    let (input, _) = (tag(RELATION), hspace, char('(')).parse(input)?;
+pub fn is_legacy(input: &str) -> bool {
+    peek(legacy_opening).parse(input).is_ok()
+}
+
+fn opening<'a>(input: &'a str, keyword: &str) -> IResult<&'a str, ()> {
+    let (input, _) = (tag(keyword), hspace, char('(')).parse(input)?;
+    Ok((input, ()))
+}
+
+fn legacy_opening(input: &str) -> IResult<&str, ()> {
+    let (input, _) = (tag(SDOC), char('[')).parse(input)?;
     Ok((input, ()))
 }
 
@@ -56,34 +88,102 @@ fn restricted_ascii(input: &str) -> IResult<&str, &str> {
     take_while1(|c: char| c.is_ascii_graphic() && !matches!(c, ',' | '=' | '(' | ')'))(input)
 }
 
-fn identifier(input: &str) -> IResult<&str, &str> {
-    restricted_ascii.parse(input)
+/// A quoted string, such as `"covers the (a), (b) cases"`, may contain any Unicode characters and
+/// the escapes `\"`, `\\`, `\n`, `\r`, `\t`, `\0`, `\'` and `\u{...}`, as in Rust.
+fn quoted(input: &str) -> IResult<&str, String> {
+    delimited(
+        char('"'),
+        fold_many0(alt((escape, none_of("\"\\"))), String::new, |mut string, c| {
+            string.push(c);
+            string
+        }),
+        char('"'),
+    )
+    .parse(input)
+}
+
+fn escape(input: &str) -> IResult<&str, char> {
+    preceded(
+        char('\\'),
+        alt((
+            value('"', char('"')),
+            value('\\', char('\\')),
+            value('\n', char('n')),
+            value('\r', char('r')),
+            value('\t', char('t')),
+            value('\0', char('0')),
+            value('\'', char('\'')),
+            map_opt(
+                delimited(tag("u{"), take_while_m_n(1, 6, |c: char| c.is_ascii_hexdigit()), char('}')),
+                |hex| u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+            ),
+        )),
+    )
+    .parse(input)
+}
+
+/// Either a quoted string or a bare token, which is unchanged from before quoting was supported.
+fn token(input: &str) -> IResult<&str, Cow<'_, str>> {
+    alt((map(quoted, Cow::Owned), map(restricted_ascii, Cow::Borrowed))).parse(input)
+}
+
+fn identifier(input: &str) -> IResult<&str, Cow<'_, str>> {
+    token.parse(input)
 }
 
 fn attribute_key(input: &str) -> IResult<&str, &str> {
-    identifier.parse(input)
+    restricted_ascii.parse(input)
 }
 
-fn attribute_value(input: &str) -> IResult<&str, &str> {
-    identifier.parse(input)
+fn attribute_value(input: &str) -> IResult<&str, Cow<'_, str>> {
+    token.parse(input)
+}
+
+/// Parse a legacy `@sdoc[REQ]` or `@sdoc[/REQ]` marker.
+pub fn legacy(input: &str) -> IResult<&str, Relation> {
+    let (input, (_, end, identifier, _)) = (
+        legacy_opening,
+        opt(char('/')),
+        take_while1(|c: char| c.is_ascii_graphic() && !matches!(c, '[' | ']' | '/')),
+        char(']'),
+    )
+        .parse(input)?;
+    let relation = Relation {
+        identifier: identifier.to_string(),
+        attributes: BTreeMap::new(),
+        role: None,
+        offset: 0, // filled in by `relations_from_doc`
+        length: 0,
+        block: Some(if end.is_some() { Block::End } else { Block::Start }),
+    };
+    Ok((input, relation))
 }
 
-pub fn relation(input: &str) -> IResult<&str, Relation> {
-    let (input, (_, _, identifier, attributes, _)) = (
-        opening,
+/// Parse a complete marker, such as `@relation(REQ-1, REQ-2, key=value)`, yielding one
+/// `Relation` per identifier, all sharing the same attributes and the keyword's role.
+pub fn relation<'a>(input: &'a str, keyword: &Keyword) -> IResult<&'a str, Vec<Relation>> {
+    let (input, (_, _, identifier, identifiers, attributes, _)) = (
+        |input| opening(input, &keyword.keyword),
         hspace, identifier,
+        many0(terminated(preceded(comma, identifier), not(equals))),
         many0((comma, attribute_key, equals, attribute_value)),
         closing,
     )
         .parse(input)?;
-    let mut relation = Relation {
-        identifier: identifier.to_string(), // result.2.to_string(),
-        attributes: BTreeMap::new(),
-    };
+    let mut map = BTreeMap::new();
     for (_, key, _, value) in attributes {
-        relation
-            .attributes
-            .insert(key.to_string(), value.to_string());
+        map.insert(key.to_string(), value.into_owned());
     }
-    Ok((input, relation))
+    let relations = std::iter::once(identifier)
+        .chain(identifiers)
+        .map(|identifier| Relation {
+            identifier: identifier.into_owned(),
+            attributes: map.clone(),
+            role: keyword.role.clone(),
+            offset: 0, // filled in by `relations_from_doc`
+            length: 0,
+            block: None,
+        })
+        .collect();
+    Ok((input, relations))
 }
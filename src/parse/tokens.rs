@@ -0,0 +1,321 @@
+use proc_macro2::{Delimiter, Spacing, TokenStream, TokenTree};
+use quote::ToTokens;
+use syn::visit_mut::{self, VisitMut};
+use syn::{Expr, ExprBlock, Item, Stmt, Token, UseTree};
+
+use crate::parse::Span;
+
+/// A single token of a file, with groups flattened into their opening and closing delimiters.
+/// Whitespace and ordinary comments are not tokens, while doc comments are `#[doc = "..."]`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Token {
+    /// The text of the token. A punctuation character not joined to the next one is followed by
+    /// a space, so that `->` and `- >` differ.
+    pub text: String,
+    pub span: Span,
+    /// Is this token part of a doc attribute, or a trailing comma that `rustfmt` may add or
+    /// remove, rather than bearing the meaning of the code?
+    pub incidental: bool,
+    /// The position of the token in the token stream, which may differ from its position in the
+    /// source for [`Tokens::normalized`].
+    order: usize,
+}
+
+/// The tokens of a file, ordered by where they are in the source.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Tokens {
+    tokens: Vec<Token>,
+}
+
+impl Tokens {
+    pub fn new(file: &syn::File) -> Self {
+        let mut tokens = vec![];
+        flatten(file.to_token_stream(), &mut tokens, Delimiter::None, false, false);
+        for (order, token) in tokens.iter_mut().enumerate() {
+            token.order = order;
+        }
+        tokens.sort_by_key(|token| token.span.start);
+        Tokens { tokens }
+    }
+
+    /// The tokens of a file after undoing the changes `rustfmt` makes that are not just to
+    /// whitespace: the braces around the single expression of a match arm or closure are
+    /// removed, each match arm has a comma, trailing commas in generics and `where` clauses are
+    /// removed, `use` groups of one item are unwrapped, and `use` groups, consecutive `use`
+    /// declarations and consecutive `mod foo;` declarations are sorted. The tokens keep their
+    /// spans in the source, but are in the normalized order.
+    pub fn normalized(file: &syn::File) -> Self {
+        let mut file = file.clone();
+        Normalize.visit_file_mut(&mut file);
+        Self::new(&file)
+    }
+
+    /// The tokens entirely inside `span`, in the order of the token stream.
+    pub fn within(&self, span: Span) -> Vec<&Token> {
+        let first = self.tokens.partition_point(|token| token.span.start < span.start);
+        let mut tokens: Vec<_> = self.tokens[first..]
+            .iter()
+            .take_while(|token| token.span.start < span.end)
+            .filter(|token| token.span.end <= span.end)
+            .collect();
+        tokens.sort_by_key(|token| token.order);
+        tokens
+    }
+}
+
+struct Normalize;
+
+impl VisitMut for Normalize {
+    fn visit_file_mut(&mut self, file: &mut syn::File) {
+        visit_mut::visit_file_mut(self, file);
+        sort_runs(&mut file.items, declaration_key);
+    }
+
+    fn visit_item_mod_mut(&mut self, module: &mut syn::ItemMod) {
+        visit_mut::visit_item_mod_mut(self, module);
+        if let Some((_, items)) = &mut module.content {
+            sort_runs(items, declaration_key);
+        }
+    }
+
+    fn visit_block_mut(&mut self, block: &mut syn::Block) {
+        visit_mut::visit_block_mut(self, block);
+        sort_runs(&mut block.stmts, |statement| match statement {
+            Stmt::Item(item) => declaration_key(item),
+            _ => None,
+        });
+    }
+
+    fn visit_expr_match_mut(&mut self, expr: &mut syn::ExprMatch) {
+        visit_mut::visit_expr_match_mut(self, expr);
+        for arm in &mut expr.arms {
+            if let Some(body) = single_expression(&arm.body) {
+                *arm.body = body;
+            }
+            arm.comma = Some(Token![,](last_span(&arm.body)));
+        }
+    }
+
+    fn visit_expr_closure_mut(&mut self, closure: &mut syn::ExprClosure) {
+        visit_mut::visit_expr_closure_mut(self, closure);
+        // The braces are needed if the return type is given
+        if closure.output == syn::ReturnType::Default
+            && let Some(body) = single_expression(&closure.body)
+        {
+            *closure.body = body;
+        }
+    }
+
+    fn visit_generics_mut(&mut self, generics: &mut syn::Generics) {
+        visit_mut::visit_generics_mut(self, generics);
+        // Generic parameters and `where` clauses are not delimited groups, so trailing commas
+        // are removed here instead
+        if let Some(param) = generics.params.pop() {
+            generics.params.push_value(param.into_value());
+        }
+        if let Some(clause) = &mut generics.where_clause
+            && let Some(predicate) = clause.predicates.pop()
+        {
+            clause.predicates.push_value(predicate.into_value());
+        }
+    }
+
+    fn visit_use_tree_mut(&mut self, tree: &mut UseTree) {
+        visit_mut::visit_use_tree_mut(self, tree);
+        let UseTree::Group(group) = tree else {
+            return;
+        };
+        let mut items: Vec<UseTree> = std::mem::take(&mut group.items).into_iter().collect();
+        // `use a::{self}` is not the same as `use a`, which also imports any function `a`
+        if let [item] = items.as_slice()
+            && !matches!(item, UseTree::Name(name) if name.ident == "self")
+        {
+            *tree = items.pop().expect("there is exactly one item");
+            return;
+        }
+        items.sort_by_cached_key(|item| item.to_token_stream().to_string());
+        for item in items {
+            let comma = Token![,](last_span(&item));
+            group.items.push_value(item);
+            group.items.push_punct(comma);
+        }
+    }
+}
+
+/// The expression of a block consisting of just that expression, such as `{ a + 1 }`.
+fn single_expression(expr: &Expr) -> Option<Expr> {
+    match expr {
+        Expr::Block(ExprBlock { attrs, label: None, block }) if attrs.is_empty() => match block.stmts.as_slice() {
+            [Stmt::Expr(expr, None)] => Some(expr.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The key to sort a `use` or `mod foo;` declaration by, among others of the same kind.
+fn declaration_key(item: &Item) -> Option<(u8, String)> {
+    match item {
+        Item::Use(item) => Some((0, format!("{} {}", item.vis.to_token_stream(), item.tree.to_token_stream()))),
+        Item::Mod(item) if item.content.is_none() => Some((1, format!("{} {}", item.vis.to_token_stream(), item.ident))),
+        _ => None,
+    }
+}
+
+/// Sort each run of consecutive elements with keys of the same kind.
+fn sort_runs<T>(elements: &mut [T], key: impl Fn(&T) -> Option<(u8, String)>) {
+    let mut start = 0;
+    while start < elements.len() {
+        let Some((kind, _)) = key(&elements[start]) else {
+            start += 1;
+            continue;
+        };
+        let end = elements[start..]
+            .iter()
+            .position(|element| key(element).is_none_or(|(other, _)| other != kind))
+            .map_or(elements.len(), |length| start + length);
+        elements[start..end].sort_by_cached_key(|element| key(element).map(|(_, key)| key));
+        start = end;
+    }
+}
+
+/// The span of the last token of a node, to give a comma added after it.
+fn last_span(node: &impl ToTokens) -> proc_macro2::Span {
+    match node.to_token_stream().into_iter().last() {
+        Some(TokenTree::Group(group)) => group.span_close(),
+        Some(tree) => tree.span(),
+        None => proc_macro2::Span::call_site(),
+    }
+}
+
+/// Keywords that may be followed by a parenthesized tuple, rather than a list of arguments.
+const KEYWORDS: &[&str] = &["break", "else", "if", "in", "match", "return", "while", "yield"];
+
+/// Flatten the token stream of a group with the given `delimiter`, marking the tokens of `#[doc]`
+/// attributes and optional commas as incidental. A comma is optional at the end of a delimited
+/// group, except in what may be a one-element tuple such as `(a,)` rather than a `list` of
+/// arguments or fields.
+fn flatten(stream: TokenStream, tokens: &mut Vec<Token>, delimiter: Delimiter, list: bool, incidental: bool) {
+    let trees: Vec<TokenTree> = stream.into_iter().collect();
+    let commas = trees.iter().filter(|tree| is_punct(tree, ',')).count();
+    let trailing = match delimiter {
+        Delimiter::Parenthesis => list || commas > 1,
+        Delimiter::Brace | Delimiter::Bracket => true,
+        Delimiter::None => false,
+    };
+    let mut doc_end = 0;
+    for (index, tree) in trees.iter().enumerate() {
+        if is_punct(tree, '#') {
+            let bang = trees.get(index + 1).is_some_and(|tree| is_punct(tree, '!'));
+            if let Some(TokenTree::Group(group)) = trees.get(index + 1 + usize::from(bang))
+                && group.delimiter() == Delimiter::Bracket
+                && matches!(group.stream().into_iter().next(), Some(TokenTree::Ident(ident)) if ident == "doc")
+            {
+                doc_end = index + 2 + usize::from(bang);
+            }
+        }
+        let incidental = incidental || index < doc_end || (is_punct(tree, ',') && index + 1 == trees.len() && trailing);
+        let span = tree.span().into();
+        match tree {
+            TokenTree::Group(group) => {
+                let (open, close) = match group.delimiter() {
+                    Delimiter::Parenthesis => ("(", ")"),
+                    Delimiter::Brace => ("{", "}"),
+                    Delimiter::Bracket => ("[", "]"),
+                    Delimiter::None => ("", ""),
+                };
+                if open.is_empty() {
+                    flatten(group.stream(), tokens, delimiter, list, incidental);
+                    continue;
+                }
+                let list = index > 0 && matches!(&trees[index - 1], TokenTree::Ident(ident) if !KEYWORDS.contains(&ident.to_string().as_str()));
+                tokens.push(Token {
+                    text: open.to_string(),
+                    span: group.span_open().into(),
+                    incidental,
+                    order: 0,
+                });
+                flatten(group.stream(), tokens, group.delimiter(), list, incidental);
+                tokens.push(Token {
+                    text: close.to_string(),
+                    span: group.span_close().into(),
+                    incidental,
+                    order: 0,
+                });
+            }
+            TokenTree::Punct(punct) => {
+                let text = match punct.spacing() {
+                    Spacing::Joint => punct.as_char().to_string(),
+                    Spacing::Alone => format!("{} ", punct.as_char()),
+                };
+                tokens.push(Token {
+                    text,
+                    span,
+                    incidental,
+                    order: 0,
+                });
+            }
+            TokenTree::Ident(ident) => tokens.push(Token {
+                text: ident.to_string(),
+                span,
+                incidental,
+                order: 0,
+            }),
+            TokenTree::Literal(literal) => tokens.push(Token {
+                text: literal.to_string(),
+                span,
+                incidental,
+                order: 0,
+            }),
+        }
+    }
+}
+
+fn is_punct(tree: &TokenTree, c: char) -> bool {
+    matches!(tree, TokenTree::Punct(punct) if punct.as_char() == c)
+}
+
+#[test]
+fn test_tokens_within() {
+    let src = "/// Docs\nfn a() -> u8 {\n    // comment\n    1 // another\n}\nfn b() {}\n";
+    let file = syn::parse_file(src).expect("a file");
+    let tokens = Tokens::new(&file);
+    let item: Span = syn::spanned::Spanned::span(&file.items[0]).into();
+    let texts: Vec<_> = tokens.within(item).iter().map(|token| token.text.as_str()).collect();
+    assert_eq!(
+        texts,
+        ["# ", "[", "doc", "= ", "\" Docs\"", "]", "fn", "a", "(", ")", "-", "> ", "u8", "{", "1", "}"]
+    );
+}
+
+#[test]
+fn test_tokens_normalized() {
+    let src = r#"/// Docs
+#[doc = "More"]
+fn a(x: u8,) -> (u8,) {
+    use std::{io, fmt::{Debug}};
+    use std::{self as s, collections::{self}};
+    match x {
+        0 => {}
+        1 => { x }
+        _ => return (f(x, [1, 2,]),),
+    }
+}
+"#;
+    let file = syn::parse_file(src).expect("a file");
+    let tokens = Tokens::normalized(&file);
+    let item: Span = syn::spanned::Spanned::span(&file.items[0]).into();
+    let meaningful: Vec<_> = tokens
+        .within(item)
+        .into_iter()
+        .filter(|token| !token.incidental)
+        .map(|token| token.text.trim_end())
+        .collect();
+    assert_eq!(
+        meaningful.join(" "),
+        "fn a ( x : u8 ) - > ( u8 , ) { \
+         use std : : { collections : : { self } , self as s } ; \
+         use std : : { fmt : : Debug , io } ; \
+         match x { 0 = > { } , 1 = > x , _ = > return ( f ( x , [ 1 , 2 ] ) , ) } }"
+    );
+}
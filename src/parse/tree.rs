@@ -1,10 +1,19 @@
-use crate::parse::Span;
+use crate::parse::cfg::Cfg;
+use crate::parse;
+use crate::parse::{LineColumn, Span};
+
+use std::path::PathBuf;
 
 use paste::paste;
-use serde::Serialize;
+use quote::ToTokens;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use syn::spanned::Spanned;
 use syn::visit::{self, Visit};
-use syn::{Attribute, File, Meta, MetaNameValue};
+use syn::punctuated::Punctuated;
+use proc_macro2::{Delimiter, TokenStream, TokenTree};
+use syn::parse::Parser;
+use syn::{Attribute, Block, Expr, File, Meta, MetaList, MetaNameValue, Token};
 
 /// Where do these come from?
 ///
@@ -22,7 +31,8 @@ use syn::{Attribute, File, Meta, MetaNameValue};
 /// //
 /// ```
 ///
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[schemars(description = "The kind of `syn` syntax tree node that the span was taken from.")]
 pub enum Scope {
     Arm,
     BareFnArg,
@@ -117,46 +127,355 @@ pub enum Scope {
     TypeParam,
     Variadic,
     Variant,
+    //
+    // These do not correspond to `syn` nodes, but to spans selected by the markers themselves.
+    //
+    /// A single line of source code, selected by `scope=line`.
+    Line,
+    /// The lines from a `scope=range_start` marker to its matching `scope=range_end` marker.
+    Range,
+    /// The lines from a legacy `@sdoc[REQ]` marker to its matching `@sdoc[/REQ]` marker.
+    Block,
+    /// Tokens inside a macro invocation that could not be parsed as Rust code, from a doc
+    /// comment to the end of what it documents.
+    Tokens,
+}
+
+impl Scope {
+    /// Is this a function item, as selected by `scope=function`?
+    pub fn is_function(self) -> bool {
+        matches!(self, Scope::ItemFn | Scope::ImplItemFn | Scope::TraitItemFn | Scope::ForeignItemFn)
+    }
+
+    /// Does this node have a header that can be told apart from its docs and body, as items,
+    /// fields and variants do?
+    pub fn has_header(self) -> bool {
+        self.is_item() || matches!(self, Scope::Field | Scope::Variant)
+    }
+
+    /// Is this an item, including an associated or foreign item?
+    pub fn is_item(self) -> bool {
+        use Scope::*;
+        matches!(
+            self,
+            ItemConst
+                | ItemEnum
+                | ItemExternCrate
+                | ItemFn
+                | ItemForeignMod
+                | ItemImpl
+                | ItemMacro
+                | ItemMod
+                | ItemStatic
+                | ItemStruct
+                | ItemTrait
+                | ItemTraitAlias
+                | ItemType
+                | ItemUnion
+                | ItemUse
+                | ImplItemConst
+                | ImplItemFn
+                | ImplItemMacro
+                | ImplItemType
+                | TraitItemConst
+                | TraitItemFn
+                | TraitItemMacro
+                | TraitItemType
+                | ForeignItemFn
+                | ForeignItemMacro
+                | ForeignItemStatic
+                | ForeignItemType
+        )
+    }
+
+    /// Is this an item that defines code, and so inherits relations with `--inherit`? Imports,
+    /// `extern crate`, `extern` blocks and macros are not.
+    pub fn is_code_item(self) -> bool {
+        use Scope::*;
+        matches!(
+            self,
+            ItemConst
+                | ItemEnum
+                | ItemFn
+                | ItemImpl
+                | ItemMod
+                | ItemStatic
+                | ItemStruct
+                | ItemTrait
+                | ItemTraitAlias
+                | ItemType
+                | ItemUnion
+                | ImplItemConst
+                | ImplItemFn
+                | ImplItemType
+                | TraitItemConst
+                | TraitItemFn
+                | TraitItemType
+                | ForeignItemFn
+                | ForeignItemStatic
+                | ForeignItemType
+        )
+    }
+
+    /// Do relations on this node cover the items inside it, as selected by `--inherit`?
+    pub fn is_container(self) -> bool {
+        matches!(self, Scope::File | Scope::ItemMod | Scope::ItemImpl | Scope::ItemTrait)
+    }
+
+    /// Is this a type-like item, as selected by `scope=class`?
+    pub fn is_class(self) -> bool {
+        matches!(self, Scope::ItemStruct | Scope::ItemEnum | Scope::ItemUnion | Scope::ItemTrait | Scope::ItemImpl)
+    }
+}
+
+/// Every visited node that can carry attributes, whether or not it has any docs.
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub scope: Scope,
+    pub span: Span,
+    /// The index of the enclosing node in [`Visitor::nodes`], if any.
+    pub parent: Option<usize>,
+    /// The combined `#[cfg(...)]` predicates of the node and all its enclosing nodes, if any.
+    pub cfg: Option<Cfg>,
+    /// The identifier of the node, for named items, fields and variants.
+    pub name: Option<String>,
+    /// The path of the node within the file, such as `["Container", "new"]`, built from the
+    /// names of the enclosing modules, types, traits and impls. Unnamed nodes have the path of
+    /// their parent.
+    pub path: Vec<String>,
+    pub parts: Parts,
+}
+
+/// The parts of a node's span, where they can be told apart.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Parts {
+    /// The outer doc comments and other attributes.
+    pub docs: Option<Span>,
+    /// The rest of an item up to its body, such as a function signature, a struct's name and
+    /// generics, or an impl's header.
+    pub header: Option<Span>,
+    /// The `{ ... }` body or block of an item.
+    pub body: Option<Span>,
+}
+
+impl Parts {
+    /// Split a node into its attributes, a header (for [`Scope::has_header`]) and a trailing
+    /// `{ ... }` body, by looking at its tokens.
+    fn of(scope: Scope, node: &impl ToTokens, attributes: &[Attribute]) -> Parts {
+        let outer: Vec<Span> = attributes
+            .iter()
+            .filter(|attribute| matches!(attribute.style, syn::AttrStyle::Outer))
+            .map(|attribute| attribute.span().into())
+            .collect();
+        let docs = outer.first().zip(outer.last()).map(|(first, last)| Span {
+            start: first.start,
+            end: last.end,
+        });
+        if !scope.has_header() {
+            return Parts { docs, ..Parts::default() };
+        }
+        let trees: Vec<TokenTree> = node.to_token_stream().into_iter().collect();
+        // Outer attributes are the leading `#` `[...]` pairs
+        let mut start = 0;
+        while let (Some(TokenTree::Punct(hash)), Some(TokenTree::Group(group))) = (trees.get(start), trees.get(start + 1))
+            && hash.as_char() == '#'
+            && group.delimiter() == Delimiter::Bracket
+        {
+            start += 2;
+        }
+        let body = match trees.last() {
+            Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Brace && trees.len() > start + 1 => Some(group),
+            _ => None,
+        };
+        let end = trees.len() - usize::from(body.is_some());
+        let header = (start < end).then(|| Span {
+            start: trees[start].span().start().into(),
+            end: trees[end - 1].span().end().into(),
+        });
+        Parts {
+            docs,
+            header,
+            body: body.map(|group| group.span().into()),
+        }
+    }
+}
+
+impl Node {
+    /// Do the names of nested nodes start with the name of this node? Items declared inside a
+    /// function are local to it, so they are named after it too, as `rustc` does.
+    fn is_namespace(&self) -> bool {
+        self.scope.is_function()
+            || matches!(
+                self.scope,
+                Scope::ItemMod | Scope::ItemImpl | Scope::ItemTrait | Scope::ItemStruct | Scope::ItemEnum | Scope::ItemUnion
+            )
+    }
+}
+
+/// The text of a single `#[doc]` attribute and where the attribute is in the source.
+#[derive(Clone, Debug)]
+pub struct Doc {
+    /// The doc string, which is empty for an `include_str!` or a `#[sdoc::relation(...)]`.
+    pub text: String,
+    pub span: Span,
+    /// What is given to `#[doc = include_str!(...)]`, if anything.
+    pub include: Option<Include>,
+    /// The arguments of a structured `#[sdoc::relation(...)]` attribute, if this is one.
+    pub relation: Option<MetaList>,
+    /// The predicate of the `#[cfg_attr(...)]` the doc was wrapped in, if any.
+    pub cfg: Option<Cfg>,
+    /// Where the text is in the source.
+    pub origin: Origin,
+}
+
+/// The argument of a `#[doc = include_str!(...)]`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Include {
+    /// A string literal, which is a path relative to the source file.
+    Path(PathBuf),
+    /// Anything else, such as `concat!(env!("CARGO_MANIFEST_DIR"), "/README.md")`, which cannot
+    /// be resolved without expanding macros.
+    Unresolved(String),
+}
+
+/// Where the text of a [`Doc`] is in the source, so that offsets in it can be mapped back to
+/// positions in the source.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Origin {
+    /// The text is in the source verbatim, starting at this position, as for a `///` comment.
+    Verbatim(LineColumn),
+    /// The text is the value of the string literal with this span, as for `#[doc = "..."]`, so
+    /// any escapes in the literal have to be decoded.
+    Literal(Span),
+    /// The text is not in the source, as for an `include_str!` or a `#[sdoc::relation(...)]`.
+    Elsewhere,
 }
 
 #[derive(Clone, Debug)]
 pub struct Place {
     pub scope: Scope,
     pub span: Span,
-    pub docs: Vec<String>,
+    pub docs: Vec<Doc>,
+    /// The index of the node in [`Visitor::nodes`] this place was taken from.
+    pub node: usize,
 }
 
 #[derive(Clone, Default, Debug)]
 pub struct Visitor {
+    pub nodes: Vec<Node>,
     pub places: Vec<Place>,
+    stack: Vec<usize>,
 }
 
 impl Visitor {
-    pub fn visit(file: &File) -> Vec<Place> {
+    pub fn visit(file: &File) -> Self {
         let mut visitor = Visitor::default();
         visitor.visit_file(file);
-        visitor.places
+        visitor
+    }
+
+    /// The index of the node itself, followed by those of each of its enclosing nodes out to the `File`.
+    pub fn ancestry(&self, node: usize) -> impl Iterator<Item = usize> + '_ {
+        std::iter::successors(Some(node), |&node| self.nodes[node].parent)
+    }
+
+    /// The innermost node whose span contains the given span, or else the `File`.
+    pub fn enclosing(&self, span: Span) -> usize {
+        self.nodes
+            .iter()
+            .rposition(|node| node.span.start <= span.start && span.end <= node.span.end)
+            .unwrap_or(0)
+    }
+
+    /// The node whose code contains a doc with the given `span` that is on `node`: the parent of
+    /// the node for an outer doc comment or attribute, or a comment leading into the node, and
+    /// the node itself for an inner one, such as `//!`.
+    pub fn code_container(&self, node: usize, span: Span) -> usize {
+        let Node { span: whole, parts, parent, .. } = &self.nodes[node];
+        let outer = span.start < whole.start
+            || parts.docs.is_some_and(|docs| docs.start <= span.start && span.end <= docs.end)
+            || parts.header.is_some_and(|header| span.end <= header.start);
+        match parent {
+            Some(parent) if outer => *parent,
+            _ => node,
+        }
+    }
+
+    /// The node that a comment with the given `span` inside `container` leads into, and where
+    /// the code of that node starts. This is `container` itself when the comment is among its
+    /// outer attributes, before its header, and otherwise the first node inside it that starts
+    /// after the comment.
+    pub fn following(&self, container: usize, span: Span) -> Option<(usize, LineColumn)> {
+        let node = &self.nodes[container];
+        if let Some(header) = node.parts.header
+            && node.span.start <= span.start
+            && span.end <= header.start
+        {
+            return Some((container, header.start));
+        }
+        self.descendants(container)
+            .find(|&node| self.nodes[node].span.start >= span.end)
+            .map(|node| (node, self.nodes[node].span.start))
+    }
+
+    /// The nodes inside `container`, at any depth, in source order.
+    pub fn descendants(&self, container: usize) -> impl Iterator<Item = usize> + '_ {
+        // Nodes are in pre-order, so the nodes inside the container immediately follow it
+        (container + 1..self.nodes.len()).take_while(move |&node| self.is_within(node, container))
     }
 
-    fn process(&mut self, scope: Scope, span: Span, attributes: &[Attribute]) {
+    fn is_within(&self, node: usize, container: usize) -> bool {
+        let mut parent = self.nodes[node].parent;
+        while let Some(node) = parent {
+            if node == container {
+                return true;
+            }
+            parent = self.nodes[node].parent;
+        }
+        false
+    }
+
+    fn enter(&mut self, scope: Scope, span: Span, attributes: &[Attribute], name: Option<String>, parts: Parts) {
+        let node = self.nodes.len();
+        let parent = self.stack.last().copied();
+        let mut predicates: Vec<Cfg> = parent.and_then(|parent| self.nodes[parent].cfg.clone()).into_iter().collect();
+        predicates.extend(attributes.iter().filter_map(cfg_predicate));
+        let path = match (&name, parent) {
+            (Some(name), _) => {
+                let namespace = parent.and_then(|parent| self.ancestry(parent).map(|node| &self.nodes[node]).find(|node| node.is_namespace()));
+                let mut path = namespace.map(|namespace| namespace.path.clone()).unwrap_or_default();
+                path.push(name.clone());
+                path
+            }
+            (None, Some(parent)) => self.nodes[parent].path.clone(),
+            (None, None) => vec![],
+        };
+        self.nodes.push(Node {
+            scope,
+            span,
+            parent,
+            cfg: (!predicates.is_empty()).then(|| Cfg::all(predicates)),
+            name,
+            path,
+            parts,
+        });
+        self.stack.push(node);
+
         let mut place = Place {
             scope,
             span,
             docs: vec![],
+            node,
         };
         // println!("\n{:?}", &place);
         for attribute in attributes {
-            if let Meta::NameValue(MetaNameValue {
-                value:
-                    syn::Expr::Lit(syn::ExprLit {
-                        lit: syn::Lit::Str(literal_string),
-                        ..
-                    }),
-                ..
-            }) = &attribute.meta
-                && attribute.path().is_ident("doc")
-            {
-                place.docs.push(literal_string.value());
+            let span = attribute.span().into();
+            if let Some(value) = doc_value(&attribute.meta) {
+                place.docs.push(value.into_doc(span, None));
+            } else if attribute.path().is_ident("cfg_attr") {
+                for (value, cfg) in cfg_attr_docs(&attribute.meta) {
+                    place.docs.push(value.into_doc(span, Some(cfg)));
+                }
             }
         }
         if !place.docs.is_empty() {
@@ -164,14 +483,241 @@ impl Visitor {
             self.places.push(place);
         }
     }
+
+    fn leave(&mut self) {
+        self.stack.pop();
+    }
+
+    /// Visit the body of a macro invocation, which `syn` leaves as opaque tokens, by parsing it
+    /// as items, statements or expressions. Otherwise, look for doc attributes in the tokens
+    /// themselves, and try again inside each delimited group. The tokens keep their spans in
+    /// the source, so the nodes found this way are where they appear to be.
+    fn tokens(&mut self, tokens: TokenStream) {
+        if let Ok(file) = syn::parse2::<File>(tokens.clone()) {
+            file.items.iter().for_each(|item| self.visit_item(item));
+        } else if let Ok(statements) = Block::parse_within.parse2(tokens.clone()) {
+            statements.iter().for_each(|statement| self.visit_stmt(statement));
+        } else if let Ok(expressions) = Punctuated::<Expr, Token![,]>::parse_terminated.parse2(tokens.clone()) {
+            expressions.iter().for_each(|expression| self.visit_expr(expression));
+        } else {
+            let trees: Vec<TokenTree> = tokens.into_iter().collect();
+            let mut index = 0;
+            while index < trees.len() {
+                match &trees[index] {
+                    TokenTree::Punct(punct) if punct.as_char() == '#' => index = self.doc_tokens(&trees, index),
+                    TokenTree::Group(group) => {
+                        self.tokens(group.stream());
+                        index += 1;
+                    }
+                    _ => index += 1,
+                }
+            }
+        }
+    }
+
+    /// Record the doc attributes starting at `trees[start]`, which is a `#`, as documenting the
+    /// tokens up to and including the next `;` or `,`, or the next `{ ... }` group. Returns the
+    /// index of the first token after the attributes.
+    fn doc_tokens(&mut self, trees: &[TokenTree], start: usize) -> usize {
+        let mut docs = vec![];
+        let mut index = start;
+        // Each attribute is `#` `[...]`, or `#` `!` `[...]` for an inner attribute
+        while let Some(TokenTree::Punct(hash)) = trees.get(index)
+            && hash.as_char() == '#'
+        {
+            let bang = matches!(trees.get(index + 1), Some(TokenTree::Punct(bang)) if bang.as_char() == '!');
+            let Some(TokenTree::Group(group)) = trees.get(index + 1 + usize::from(bang)) else {
+                break;
+            };
+            if group.delimiter() != Delimiter::Bracket {
+                break;
+            }
+            let span = Span {
+                start: hash.span().start().into(),
+                end: group.span().end().into(),
+            };
+            if let Some(value) = syn::parse2::<Meta>(group.stream()).ok().as_ref().and_then(doc_value) {
+                docs.push(value.into_doc(span, None));
+            }
+            index += 2 + usize::from(bang);
+        }
+        if index == start {
+            return start + 1;
+        }
+        let end = trees[index..]
+            .iter()
+            .find(|tree| match tree {
+                TokenTree::Punct(punct) => matches!(punct.as_char(), ';' | ','),
+                TokenTree::Group(group) => group.delimiter() == Delimiter::Brace,
+                _ => false,
+            })
+            .or(trees[index..].last())
+            .unwrap_or(&trees[index - 1]);
+        if !docs.is_empty() {
+            self.places.push(Place {
+                scope: Scope::Tokens,
+                span: Span {
+                    start: trees[start].span().start().into(),
+                    end: end.span().end().into(),
+                },
+                docs,
+                node: self.stack.last().copied().unwrap_or(0),
+            });
+        }
+        index
+    }
+}
+
+/// The value of a `doc = ...` attribute, or a structured `sdoc::relation(...)` attribute.
+enum DocValue {
+    /// A string literal, as for `///` comments, and its span.
+    Text(String, Span),
+    /// The argument of an `include_str!(...)`.
+    Include(Include),
+    /// The arguments of `sdoc::relation(...)`.
+    Relation(MetaList),
+}
+
+impl DocValue {
+    fn into_doc(self, span: Span, cfg: Option<Cfg>) -> Doc {
+        let mut doc = Doc {
+            text: String::new(),
+            span,
+            include: None,
+            relation: None,
+            cfg,
+            origin: Origin::Elsewhere,
+        };
+        match self {
+            DocValue::Text(text, literal) => {
+                // The literal of a doc comment has the span of the whole comment, and its text
+                // follows the three characters of `///`, `//!`, `/**` or `/*!`
+                doc.origin = if literal == span {
+                    Origin::Verbatim(LineColumn {
+                        column: span.start.column + 3,
+                        ..span.start
+                    })
+                } else {
+                    Origin::Literal(literal)
+                };
+                doc.text = text;
+            }
+            DocValue::Include(include) => doc.include = Some(include),
+            DocValue::Relation(list) => doc.relation = Some(list),
+        }
+        doc
+    }
+}
+
+fn doc_value(meta: &Meta) -> Option<DocValue> {
+    if let Meta::List(list) = meta
+        && is_relation_attribute(&list.path)
+    {
+        return Some(DocValue::Relation(list.clone()));
+    }
+    let Meta::NameValue(MetaNameValue { path, value, .. }) = meta else {
+        return None;
+    };
+    if !path.is_ident("doc") {
+        return None;
+    }
+    match value {
+        syn::Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::Str(literal_string),
+            ..
+        }) => Some(DocValue::Text(literal_string.value(), literal_string.span().into())),
+        syn::Expr::Macro(syn::ExprMacro { mac, .. }) if mac.path.is_ident("include_str") => Some(DocValue::Include(match mac.parse_body::<syn::LitStr>() {
+            Ok(literal_string) => Include::Path(PathBuf::from(literal_string.value())),
+            Err(_) => Include::Unresolved(compact(&mac.tokens.to_string())),
+        })),
+        _ => None,
+    }
+}
+
+/// The name of an impl as it would be written in a path: the self type for an inherent impl,
+/// such as `Container`, or the qualified form for a trait impl, such as `<Container as Display>`.
+fn impl_name(node: &syn::ItemImpl) -> String {
+    let self_type = match node.self_ty.as_ref() {
+        syn::Type::Path(syn::TypePath { qself: None, path }) => path_name(path),
+        self_type => compact(&self_type.to_token_stream().to_string()),
+    };
+    match &node.trait_ {
+        Some((bang, path, _)) => format!("<{self_type} as {}{}>", if bang.is_some() { "!" } else { "" }, path_name(path)),
+        None => self_type,
+    }
+}
+
+/// A path without any generic arguments, such as `fmt::Display` for `fmt::Display<'a>`.
+fn path_name(path: &syn::Path) -> String {
+    let segments: Vec<_> = path.segments.iter().map(|segment| segment.ident.to_string()).collect();
+    segments.join("::")
+}
+
+/// Remove the spaces `proc_macro2` puts between tokens, except those between words.
+fn compact(tokens: &str) -> String {
+    let word = |c: char| c.is_alphanumeric() || c == '_';
+    let chars: Vec<char> = tokens.chars().collect();
+    let mut compacted = String::new();
+    for (index, &c) in chars.iter().enumerate() {
+        if c == ' ' && !(index > 0 && word(chars[index - 1]) && chars.get(index + 1).is_some_and(|&next| word(next) || next == '\'')) {
+            continue;
+        }
+        compacted.push(c);
+    }
+    compacted
+}
+
+/// Is this the path of the structured attribute, `sdoc::relation` (or `::sdoc::relation`)?
+fn is_relation_attribute(path: &syn::Path) -> bool {
+    path_name(path) == parse::ATTRIBUTE
+}
+
+/// The predicate of a `#[cfg(predicate)]` attribute. Malformed attributes are ignored.
+fn cfg_predicate(attribute: &Attribute) -> Option<Cfg> {
+    if !attribute.path().is_ident("cfg") {
+        return None;
+    }
+    Cfg::from_meta(&attribute.parse_args().ok()?)
+}
+
+/// The doc strings inside a `cfg_attr(predicate, attr, ...)` attribute, which may be nested, each
+/// with the combined predicate under which it applies. Malformed attributes are ignored.
+fn cfg_attr_docs(meta: &Meta) -> Vec<(DocValue, Cfg)> {
+    let Meta::List(list) = meta else {
+        return vec![];
+    };
+    let Ok(nested) = list.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated) else {
+        return vec![];
+    };
+    let mut nested = nested.into_iter();
+    let Some(predicate) = nested.next().as_ref().and_then(Cfg::from_meta) else {
+        return vec![];
+    };
+    let mut docs = vec![];
+    for meta in nested {
+        if let Some(value) = doc_value(&meta) {
+            docs.push((value, predicate.clone()));
+        } else if meta.path().is_ident("cfg_attr") {
+            for (value, inner) in cfg_attr_docs(&meta) {
+                docs.push((value, Cfg::all([predicate.clone(), inner])));
+            }
+        }
+    }
+    docs
 }
 
 macro_rules! visit {
     ($ident:ident) => {
+        visit!($ident, |_| None);
+    };
+    ($ident:ident, $name:expr) => {
         paste! {
             fn [<visit_ $ident:snake>](&mut self, node: &'ast syn::$ident) {
-                self.process(Scope::$ident, node.span().into(), &node.attrs);
+                let name: fn(&syn::$ident) -> Option<String> = $name;
+                let parts = Parts::of(Scope::$ident, node, &node.attrs);
+                self.enter(Scope::$ident, node.span().into(), &node.attrs, name(node), parts);
                 visit::[<visit_ $ident:snake>](self, node);
+                self.leave();
             }
         }
     };
@@ -181,7 +727,7 @@ macro_rules! visit {
 ///
 impl<'ast> Visit<'ast> for Visitor {
     //
-    // These should be one-to-one with the `Scope` enum.
+    // These should be one-to-one with the `syn` nodes in the `Scope` enum.
     //
     visit!(Arm);
     visit!(BareFnArg);
@@ -227,32 +773,32 @@ impl<'ast> Visit<'ast> for Visitor {
     visit!(ExprUnsafe);
     visit!(ExprWhile);
     visit!(ExprYield);
-    visit!(Field);
+    visit!(Field, |node| node.ident.as_ref().map(ToString::to_string));
     visit!(FieldPat);
     visit!(FieldValue);
     visit!(File);
-    visit!(ForeignItemFn);
+    visit!(ForeignItemFn, |node| Some(node.sig.ident.to_string()));
     visit!(ForeignItemMacro);
-    visit!(ForeignItemStatic);
-    visit!(ForeignItemType);
-    visit!(ImplItemConst);
-    visit!(ImplItemFn);
+    visit!(ForeignItemStatic, |node| Some(node.ident.to_string()));
+    visit!(ForeignItemType, |node| Some(node.ident.to_string()));
+    visit!(ImplItemConst, |node| Some(node.ident.to_string()));
+    visit!(ImplItemFn, |node| Some(node.sig.ident.to_string()));
     visit!(ImplItemMacro);
-    visit!(ImplItemType);
-    visit!(ItemConst);
-    visit!(ItemEnum);
-    visit!(ItemExternCrate);
-    visit!(ItemFn);
+    visit!(ImplItemType, |node| Some(node.ident.to_string()));
+    visit!(ItemConst, |node| Some(node.ident.to_string()));
+    visit!(ItemEnum, |node| Some(node.ident.to_string()));
+    visit!(ItemExternCrate, |node| Some(node.ident.to_string()));
+    visit!(ItemFn, |node| Some(node.sig.ident.to_string()));
     visit!(ItemForeignMod);
-    visit!(ItemImpl);
-    visit!(ItemMacro);
-    visit!(ItemMod);
-    visit!(ItemStatic);
-    visit!(ItemStruct);
-    visit!(ItemTrait);
-    visit!(ItemTraitAlias);
-    visit!(ItemType);
-    visit!(ItemUnion);
+    visit!(ItemImpl, |node| Some(impl_name(node)));
+    visit!(ItemMacro, |node| node.ident.as_ref().map(ToString::to_string));
+    visit!(ItemMod, |node| Some(node.ident.to_string()));
+    visit!(ItemStatic, |node| Some(node.ident.to_string()));
+    visit!(ItemStruct, |node| Some(node.ident.to_string()));
+    visit!(ItemTrait, |node| Some(node.ident.to_string()));
+    visit!(ItemTraitAlias, |node| Some(node.ident.to_string()));
+    visit!(ItemType, |node| Some(node.ident.to_string()));
+    visit!(ItemUnion, |node| Some(node.ident.to_string()));
     visit!(ItemUse);
     visit!(LifetimeParam);
     visit!(Local);
@@ -269,11 +815,30 @@ impl<'ast> Visit<'ast> for Visitor {
     visit!(PatWild);
     visit!(Receiver);
     visit!(StmtMacro);
-    visit!(TraitItemConst);
-    visit!(TraitItemFn);
+    visit!(TraitItemConst, |node| Some(node.ident.to_string()));
+    visit!(TraitItemFn, |node| Some(node.sig.ident.to_string()));
     visit!(TraitItemMacro);
-    visit!(TraitItemType);
+    visit!(TraitItemType, |node| Some(node.ident.to_string()));
     visit!(TypeParam);
     visit!(Variadic);
-    visit!(Variant);
+    visit!(Variant, |node| Some(node.ident.to_string()));
+
+    //
+    // Macro invocations are not nodes themselves, but their bodies may contain nodes.
+    //
+    fn visit_macro(&mut self, node: &'ast syn::Macro) {
+        if !is_template(&node.path) {
+            self.tokens(node.tokens.clone());
+        }
+        visit::visit_macro(self, node);
+    }
+}
+
+/// Macros whose bodies are templates for generating code rather than code: the patterns and
+/// templates of a `macro_rules!` definition, and the quasi-quoted tokens of `quote!` and `syn`.
+const TEMPLATES: &[&str] = &["macro_rules", "quote", "quote_spanned", "parse_quote", "parse_quote_spanned"];
+
+/// Is this the path of a macro whose body is a template, such as `quote!` or `quote::quote!`?
+fn is_template(path: &syn::Path) -> bool {
+    path.segments.last().is_some_and(|segment| TEMPLATES.iter().any(|template| segment.ident == template))
 }
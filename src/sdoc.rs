@@ -1,25 +1,34 @@
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 
+use std::borrow::Cow;
+use std::cmp::Reverse;
 use std::collections::BTreeMap;
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::num::NonZeroUsize;
+use std::path::{Component, Path, PathBuf};
 
-use serde::Serialize;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 
 use crate::parse;
-use crate::parse::tree::Scope;
-use crate::parse::Span;
+use crate::parse::cfg::Cfg;
+use crate::parse::comment::Comment;
+use crate::parse::lines::Lines;
+use crate::parse::tokens::Tokens;
+use crate::parse::tree::{Doc, Include, Node, Origin, Parts, Place, Scope, Visitor};
+use crate::parse::{Columns, Keyword, LineColumn, Span};
 
 /// A type-tagged hexadecimal hash.
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum Hash {
+    /// The lowercase hexadecimal SHA-256 digest.
     Sha256(String),
 }
 
-impl From<&Vec<u8>> for Hash {
-    fn from(bytes: &Vec<u8>) -> Self {
+impl From<&[u8]> for Hash {
+    fn from(bytes: &[u8]) -> Self {
         let mut hasher = Sha256::new();
         hasher.update(bytes);
         let hash = format!("{:x}", hasher.finalize());
@@ -27,40 +36,340 @@ impl From<&Vec<u8>> for Hash {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize)]
+impl From<&Vec<u8>> for Hash {
+    fn from(bytes: &Vec<u8>) -> Self {
+        Self::from(bytes.as_slice())
+    }
+}
+
+/// The version of the JSON output format, stored in [`Report::version`].
+///
+/// The compatibility policy is:
+///
+/// - The version is incremented whenever a change could break an existing consumer, such as
+///   removing or renaming a field, or changing the type or meaning of a field.
+/// - New fields and new [`Scope`] values may be added without incrementing the version. A new
+///   field may always be present, as [`Relation::index`] is, or be omitted when empty, but it
+///   has a default so that files written before it can still be read. Consumers should ignore
+///   fields they do not recognize.
+/// - Consumers should reject files with a version newer than the one they understand.
+///   [`Report::from_json`] does this, and migrates older files where possible.
+///
+/// Version `0` is the unversioned output of earlier releases: a bare array of [`Relations`].
+pub const FORMAT_VERSION: u32 = 1;
+
+/// The top-level JSON document: a format version and the relations found in each file.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+pub struct Report {
+    /// The format version, see [`FORMAT_VERSION`] for the compatibility policy.
+    pub version: u32,
+    /// The relations found in each source file.
+    pub files: Vec<Relations>,
+}
+
+impl Report {
+    /// Wrap the relations found in each file in a report with the current [`FORMAT_VERSION`].
+    pub fn new(files: Vec<Relations>) -> Self {
+        Report {
+            version: FORMAT_VERSION,
+            files,
+        }
+    }
+
+    /// Load a report from JSON, migrating older format versions to the current one and
+    /// rejecting versions newer than [`FORMAT_VERSION`].
+    pub fn from_json(json: &str) -> Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(json).context("failed to parse JSON")?;
+        if value.is_array() {
+            // Version 0 is a bare array of `Relations`
+            let files = serde_json::from_value(value).context("failed to load version 0 report")?;
+            return Ok(Report::new(files));
+        }
+        let version = value
+            .get("version")
+            .and_then(serde_json::Value::as_u64)
+            .ok_or_else(|| anyhow!("missing or invalid format version"))?;
+        if version > u64::from(FORMAT_VERSION) {
+            bail!("unsupported format version: {version} (newest supported is {FORMAT_VERSION})");
+        }
+        serde_json::from_value(value).with_context(|| format!("failed to load version {version} report"))
+    }
+}
+
+/// The [JSON Schema](https://json-schema.org/) describing a [`Report`].
+pub fn json_schema() -> schemars::Schema {
+    schemars::schema_for!(Report)
+}
+
+/// The relations found in a single source file.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
 pub struct Relations {
+    /// The path of the source file, relative to the prefix.
     pub file: PathBuf,
+    /// The hash of the whole source file.
     pub hash: Hash,
+    /// The relations found in the source file.
     pub relations: Vec<Relation>,
+    /// Problems found in the source file that did not stop it from being processed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<Warning>,
+    /// The module path of the file, such as `crate::storage`, when it was found by following
+    /// `mod` declarations from a crate root.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub module: Option<String>,
+    /// Is the file in a crate's directory without being reachable from any of its crate roots,
+    /// so that it is not compiled?
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub orphan: bool,
+    /// The unit in which the columns of all the spans are counted.
+    #[serde(default, skip_serializing_if = "Columns::is_chars")]
+    pub columns: Columns,
+}
+
+/// A problem with a marker, such as a `scope=` that does not apply where the marker is.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+pub struct Warning {
+    pub message: String,
+    /// The span of the doc comment or attribute containing the marker.
+    pub span: Span,
 }
 
 /// This is the information we require to [link source code to requirements](https://strictdoc.readthedocs.io/en/stable/stable/docs/strictdoc_01_user_guide.html#10.2-Linking-source-code-to-requirements).
-#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
 pub struct Relation {
+    /// The position of the relation in [`Relations::relations`].
+    #[serde(default)]
+    pub index: usize,
+    /// The index of the relation whose span most closely encloses this one, if any. Where
+    /// several relations share that span, the first of them is the parent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<usize>,
+    /// The requirement identifier.
     #[serde(rename = "relation")]
     pub ident: String,
+    /// Any `key=value` attributes given in the marker.
     #[serde(
         default,
         skip_serializing_if = "BTreeMap::is_empty",
         rename = "attributes"
     )]
     pub attrs: BTreeMap<String, String>,
+    /// The role given by the marker keyword, such as `implements` for `@implements(...)`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    /// The normalized `#[cfg]` predicate under which the relation applies, such as `feature = "x"`,
+    /// combining those of all the enclosing items with that of any `#[cfg_attr]` around the marker.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cfg: Option<String>,
+    /// The identifier of the item the span was taken from, such as `new`, if it is a named item,
+    /// field or variant.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub item: Option<String>,
+    /// The fully qualified path of the item the relation applies to, or of the innermost named
+    /// item enclosing it, such as `crate::storage::Container::new`.
+    #[serde(default)]
+    pub path: String,
+    /// The kind of syntax tree node the span was taken from.
     pub scope: Scope,
+    /// The span of source code the relation applies to.
+    pub span: Span,
+    /// The part of the span with the item's doc comments and other attributes, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub docs: Option<Span>,
+    /// The part of the span with the item's header, such as a function signature, without its
+    /// docs or body.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub header: Option<Span>,
+    /// The part of the span with the item's `{ ... }` body or block, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<Span>,
+    /// The hash of the exact source text in the span, which changes with any edit to it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text_hash: Option<Hash>,
+    /// The hash of the tokens in the span, separated by newlines, which ignores changes to
+    /// whitespace and ordinary comments, but not to doc comments.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_hash: Option<Hash>,
+    /// The hash of the meaning-bearing tokens in the span, which ignores doc comments, trailing
+    /// commas and the spacing of punctuation too, and undoes the other changes `rustfmt` makes
+    /// with its default settings, so that it survives reformatting and reflowed docs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<Hash>,
+    /// The source text in the span, possibly truncated. Only emitted with [`Options::excerpt`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub excerpt: Option<String>,
+    /// The span of the marker itself, such as `@relation(REQ-1)`. For a marker in an included
+    /// file or a `#[sdoc::relation(...)]`, this is the span of the attribute.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub marker: Option<Span>,
+    /// Where the marker is, for a marker in a file included with `#[doc = include_str!("...")]`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub include: Option<Location>,
+    /// The relation this one was derived from, for a relation on an item inside an impl, trait
+    /// or module that has a relation itself. Only emitted with [`Options::inherit`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inherited: Option<Inherited>,
+}
+
+/// Where an inherited relation was derived from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+pub struct Inherited {
+    /// The index of the relation on the enclosing impl, trait or module.
+    pub from: usize,
+    /// The span of the relation on the enclosing impl, trait or module.
     pub span: Span,
 }
 
+/// A line in a file other than the source file, such as an included Markdown file.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+pub struct Location {
+    /// The path of the file, relative to the prefix.
+    pub file: PathBuf,
+    pub line: NonZeroUsize,
+}
+
+/// Options controlling how relations are found.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Options {
+    /// The marker keywords to look for, each with the role it gives to its relations.
+    pub keywords: Vec<Keyword>,
+    /// Also emit the relations on an impl, trait or module (including a file's `//!` docs) on
+    /// each of the items inside it, marked as [`Relation::inherited`].
+    pub inherit: bool,
+    /// The unit to count columns in.
+    pub columns: Columns,
+    /// Embed the source text of each span in its relation, as [`Relation::excerpt`].
+    pub excerpt: Option<Excerpt>,
+}
+
+/// The limits on the size of a [`Relation::excerpt`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Excerpt {
+    /// The number of lines to keep of a longer span, though the docs and header of an item, such
+    /// as a function signature, are always kept.
+    pub lines: usize,
+    /// The number of bytes to keep at most, which takes precedence over `lines`.
+    pub bytes: usize,
+}
+
+impl Default for Excerpt {
+    fn default() -> Self {
+        Excerpt { lines: 20, bytes: 4096 }
+    }
+}
+
+impl Excerpt {
+    /// The excerpt of `text`, keeping at least its first `keep` bytes within the size limit. A
+    /// truncated excerpt ends with a `// ... (N more line(s))` line.
+    fn of(&self, text: &str, keep: usize) -> String {
+        if text.lines().count() <= self.lines && text.len() <= self.bytes {
+            return text.to_string();
+        }
+        let lines = match self.lines.checked_sub(1) {
+            Some(n) => text.match_indices('\n').nth(n).map_or(text.len(), |(end, _)| end),
+            None => 0,
+        };
+        let kept = text[keep..].find('\n').map_or(text.len(), |end| keep + end);
+        let mut end = lines.max(kept).min(self.bytes);
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        if end >= text.len() {
+            return text.to_string();
+        }
+        let more = text[end..].trim_start_matches('\n').lines().count();
+        format!("{}\n// ... ({more} more line(s))", &text[..end])
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            keywords: vec![Keyword::default()],
+            inherit: false,
+            columns: Columns::default(),
+            excerpt: None,
+        }
+    }
+}
+
+impl Options {
+    /// Look for an additional marker keyword, replacing any registered keyword of the same name,
+    /// so that the role of the default `@relation` keyword can be changed too.
+    pub fn register(&mut self, keyword: Keyword) {
+        self.keywords.retain(|registered| registered.keyword != keyword.keyword);
+        self.keywords.push(keyword);
+    }
+}
+
 /// Analyze the provided Rust source file and find relations between items, storing file paths relative to the crate root.
 pub fn find_relations<P: AsRef<Path>, R: AsRef<Path>>(path: &P, prefix: &R) -> Result<Relations> {
+    find_relations_with(path, prefix, &Options::default())
+}
+
+/// Analyze Rust source code that is already in memory and find relations between items.
+///
+/// The `path` is stored as-is in [`Relations::file`] and is only used for reporting.
+pub fn find_relations_in_str<P: AsRef<Path>>(path: &P, src: &str) -> Result<Relations> {
+    find_relations_in_str_with(path, src, &Options::default())
+}
+
+/// Like [`find_relations`], but with the given options.
+pub fn find_relations_with<P: AsRef<Path>, R: AsRef<Path>>(path: &P, prefix: &R, options: &Options) -> Result<Relations> {
     let path = path.as_ref();
+    let src = read_source(path)?;
+
+    // Determine the path to store in `Relation.file` relative to the crate root
+    let relative_path = path.strip_prefix(prefix).unwrap_or(path);
+
+    find_relations_in(relative_path, path, &src, options, None)
+}
+
+/// Find the relations in the crate in the directory `root`, following `mod` declarations from
+/// the crate roots to find each file and its module path, as described in
+/// [`parse::module::resolve`]. Files that are not reachable are included with
+/// [`Relations::orphan`] set, and a warning.
+pub fn find_relations_in_crate<P: AsRef<Path>, R: AsRef<Path>>(root: &P, prefix: &R, options: &Options) -> Result<Vec<Relations>> {
     let prefix = prefix.as_ref();
+    let resolved = parse::module::resolve(root.as_ref())?;
+    let mut found = vec![];
+    for module in resolved.modules {
+        let src = read_source(&module.file)?;
+        let relative_path = module.file.strip_prefix(prefix).unwrap_or(&module.file);
+        let mut relations = find_relations_in(relative_path, &module.file, &src, options, Some(&module.path))?;
+        let lines = Lines::new(&src);
+        for (span, message) in module.missing {
+            let span = lines.convert_span(span, options.columns);
+            relations.warnings.push(Warning { message, span });
+        }
+        relations.module = Some(module.path);
+        found.push(relations);
+    }
+    for orphan in resolved.orphans {
+        let src = read_source(&orphan)?;
+        let relative_path = orphan.strip_prefix(prefix).unwrap_or(&orphan);
+        let mut relations = find_relations_in(relative_path, &orphan, &src, options, None)?;
+        let lines = Lines::new(&src);
+        relations.warnings.insert(
+            0,
+            Warning {
+                message: format!("orphan file is not reachable from any crate root, and has {} relation(s)", relations.relations.len()),
+                span: lines.convert_span(lines.file_span(), options.columns),
+            },
+        );
+        relations.orphan = true;
+        found.push(relations);
+    }
+    found.sort_by(|a, b| a.file.cmp(&b.file));
+    Ok(found)
+}
 
+/// Read a source file, which must be UTF-8.
+fn read_source(path: &Path) -> Result<String> {
     // Read the file into a byte array
     let bytes = fs::read(path)
         .with_context(|| format!("failed to read source file: {}", path.display()))?;
 
-    // Calculate SHA256 hash as hexadecimal string
-    let hash = Hash::from(&bytes);
-
     // Convert byte buffer to string
     let src = String::from_utf8(bytes).with_context(|| {
         format!(
@@ -69,7 +378,23 @@ pub fn find_relations<P: AsRef<Path>, R: AsRef<Path>>(path: &P, prefix: &R) -> R
         )
     })?;
 
-    let file_ast = syn::parse_file(&src).map_err(|err| {
+    Ok(src)
+}
+
+/// Like [`find_relations_in_str`], but with the given options.
+pub fn find_relations_in_str_with<P: AsRef<Path>>(path: &P, src: &str, options: &Options) -> Result<Relations> {
+    find_relations_in(path.as_ref(), path.as_ref(), src, options, None)
+}
+
+/// Find the relations in `src`, reporting it as `path`. Files included with `include_str!` are
+/// read relative to `source`, the path the source was actually read from. Without a resolved
+/// `module` path, the module is guessed from the file's path.
+fn find_relations_in(path: &Path, source: &Path, src: &str, options: &Options, module: Option<&str>) -> Result<Relations> {
+
+    // Calculate SHA256 hash as hexadecimal string
+    let hash = Hash::from(src.as_bytes());
+
+    let file_ast = syn::parse_file(src).map_err(|err| {
         let span = err.span();
         let start = span.start();
         anyhow!(
@@ -81,30 +406,1388 @@ pub fn find_relations<P: AsRef<Path>, R: AsRef<Path>>(path: &P, prefix: &R) -> R
         )
     })?;
 
-    // Determine the path to store in `Relation.file` relative to the crate root
-    let relative_path = path.strip_prefix(prefix).unwrap_or(path);
-
     let mut relations = Relations {
-        file: relative_path.to_path_buf(),
-        hash: hash.clone(),
+        file: path.to_path_buf(),
+        hash,
         relations: vec![],
+        warnings: vec![],
+        module: None,
+        orphan: false,
+        columns: options.columns,
     };
 
     // Parse the file and collect all the places
-    let places = parse::tree::Visitor::visit(&file_ast);
-    for place in &places {
+    let module = module.map_or_else(|| module_path(path), str::to_string);
+    let lines = Lines::new(src);
+    let visitor = parse::tree::Visitor::visit(&file_ast);
+    let comments = parse::comment::comments(src, &lines);
+    let mut commented = vec![];
+    for comment in &comments {
+        // Ordinary comments are free-form text, so what looks like a malformed marker in one only
+        // warrants a warning, unlike in a doc comment
+        match parse::relations_from_doc_with(comment.text, &options.keywords) {
+            Ok(found) if !found.is_empty() => commented.push(attach_comment(&visitor, src, &lines, &comments, comment)),
+            Ok(_) => {}
+            Err(err) => relations.warnings.push(Warning {
+                message: format!("ignored comment with a {err}"),
+                span: comment.span,
+            }),
+        }
+    }
+    let mut bounds = vec![];
+    for place in visitor.places.iter().chain(&commented) {
         for doc in &place.docs {
-            for relation in parse::relations_from_doc(doc)? {
-                let relation = Relation {
-                    ident: relation.identifier,
-                    attrs: relation.attributes,
-                    scope: place.scope,
-                    span: place.span,
+            let (text, included) = match &doc.include {
+                None => (Cow::Borrowed(doc.text.as_str()), None),
+                Some(Include::Unresolved(argument)) => {
+                    relations.warnings.push(Warning {
+                        message: format!("cannot resolve include_str! argument `{argument}`"),
+                        span: doc.span,
+                    });
+                    continue;
+                }
+                Some(Include::Path(include)) => match fs::read_to_string(relative_to(source, include)) {
+                    Ok(text) => (Cow::Owned(text), Some(normalize(&relative_to(path, include)))),
+                    Err(err) => {
+                        relations.warnings.push(Warning {
+                            message: format!("failed to read included file {}: {err}", include.display()),
+                            span: doc.span,
+                        });
+                        continue;
+                    }
+                },
+            };
+            let found = match &doc.relation {
+                Some(list) => parse::relations_from_attribute(list)?,
+                None => parse::relations_from_doc_with(&text, &options.keywords)?,
+            };
+            for relation in found {
+                let include = included.as_ref().map(|file| Location {
+                    file: file.clone(),
+                    line: NonZeroUsize::MIN.saturating_add(text[..relation.offset].matches('\n').count()),
+                });
+                let marker = marker_span(src, &lines, doc, &relation);
+                if let Some((scope, start)) = range_bound(&relation) {
+                    bounds.push(Bound {
+                        relation,
+                        scope,
+                        start,
+                        marker,
+                        doc: doc.span,
+                        cfg: condition(visitor.nodes[place.node].cfg.as_ref(), doc.cfg.as_ref()),
+                        path: item_path(&module, &visitor.nodes[place.node]),
+                        container: visitor.code_container(place.node, doc.span),
+                    });
+                    continue;
+                }
+                let (scope, span, node) = match select_scope(&visitor, &lines, place, marker, &relation) {
+                    Ok(selected) => selected,
+                    Err(message) => {
+                        relations.warnings.push(Warning { message, span: doc.span });
+                        (place.scope, place.span, place.node)
+                    }
+                };
+                let from = relations.relations.len();
+                let inherited = if options.inherit && visitor.nodes[node].scope == scope && scope.is_container() {
+                    let inherited = Inherited { from, span };
+                    visitor
+                        .descendants(node)
+                        .filter(|&descendant| visitor.nodes[descendant].scope.is_code_item())
+                        .map(|descendant| {
+                            let descendant = &visitor.nodes[descendant];
+                            Relation {
+                                marker: Some(marker),
+                                inherited: Some(inherited),
+                                ..relation_on(&module, descendant, descendant.scope, descendant.span, relation.clone(), doc, include.clone())
+                            }
+                        })
+                        .collect()
+                } else {
+                    vec![]
                 };
-                relations.relations.push(relation);
+                relations.relations.push(Relation {
+                    marker: Some(marker),
+                    ..relation_on(&module, &visitor.nodes[node], scope, span, relation, doc, include)
+                });
+                relations.relations.extend(inherited);
             }
         }
     }
+    pair_ranges(&lines, bounds, &mut relations);
+    link_parents(&mut relations.relations);
+    hash_spans(src, &lines, &Tokens::new(&file_ast), &Tokens::normalized(&file_ast), &mut relations.relations);
+    if let Some(excerpt) = &options.excerpt {
+        excerpt_spans(src, &lines, excerpt, &mut relations.relations);
+    }
+    convert_columns(&lines, &mut relations);
 
     Ok(relations)
 }
+
+/// Fill in the hashes of the source text and tokens in the span of each relation, and its
+/// fingerprint from the `normalized` tokens.
+fn hash_spans(src: &str, lines: &Lines, tokens: &Tokens, normalized: &Tokens, relations: &mut [Relation]) {
+    for relation in relations {
+        let text = &src[lines.offset(relation.span.start)..lines.offset(relation.span.end)];
+        relation.text_hash = Some(Hash::from(text.as_bytes()));
+        let mut all = String::new();
+        for token in tokens.within(relation.span) {
+            all.push_str(&token.text);
+            all.push('\n');
+        }
+        relation.token_hash = Some(Hash::from(all.as_bytes()));
+        let mut meaningful = String::new();
+        for token in normalized.within(relation.span).into_iter().filter(|token| !token.incidental) {
+            meaningful.push_str(token.text.trim_end());
+            meaningful.push('\n');
+        }
+        relation.fingerprint = Some(Hash::from(meaningful.as_bytes()));
+    }
+}
+
+/// Fill in the excerpt of the source text in the span of each relation.
+fn excerpt_spans(src: &str, lines: &Lines, excerpt: &Excerpt, relations: &mut [Relation]) {
+    for relation in relations {
+        let start = lines.offset(relation.span.start);
+        let text = &src[start..lines.offset(relation.span.end)];
+        let keep = relation.header.map_or(0, |header| lines.offset(header.end).saturating_sub(start));
+        relation.excerpt = Some(excerpt.of(text, keep.min(text.len())));
+    }
+}
+
+/// Fill in the byte offsets of all the spans, and count their columns in [`Relations::columns`].
+/// This is done last, since spans are compared by line and column while finding relations.
+fn convert_columns(lines: &Lines, relations: &mut Relations) {
+    let columns = relations.columns;
+    let convert = |span: &mut Span| *span = lines.convert_span(*span, columns);
+    for relation in &mut relations.relations {
+        convert(&mut relation.span);
+        relation.docs.iter_mut().for_each(convert);
+        relation.header.iter_mut().for_each(convert);
+        relation.body.iter_mut().for_each(convert);
+        relation.marker.iter_mut().for_each(convert);
+        relation.inherited.iter_mut().for_each(|inherited| convert(&mut inherited.span));
+    }
+    for warning in &mut relations.warnings {
+        convert(&mut warning.span);
+    }
+}
+
+/// A relation applying to `span`, which was taken from `node` itself, or from the line or file
+/// around it.
+fn relation_on(module: &str, node: &Node, scope: Scope, span: Span, relation: parse::Relation, doc: &Doc, include: Option<Location>) -> Relation {
+    // Only a span taken from the node itself is that item, with its parts
+    let own = node.scope == scope;
+    let parts = if own { node.parts } else { Parts::default() };
+    Relation {
+        index: 0, // filled in by `link_parents`
+        parent: None,
+        ident: relation.identifier,
+        attrs: relation.attributes,
+        role: relation.role,
+        cfg: condition(node.cfg.as_ref(), doc.cfg.as_ref()),
+        item: own.then(|| node.name.clone()).flatten(),
+        path: item_path(module, node),
+        scope,
+        span,
+        docs: parts.docs,
+        header: parts.header,
+        body: parts.body,
+        text_hash: None, // filled in by `hash_spans`
+        token_hash: None,
+        fingerprint: None,
+        excerpt: None,
+        marker: None,
+        include,
+        inherited: None,
+    }
+}
+
+/// Attach an ordinary comment to the node that directly follows it, so that it applies to the
+/// same span as a doc comment in the same position would. Comments that trail code on the same
+/// line, or that are not directly followed by a node, apply to their own line.
+fn attach_comment(visitor: &Visitor, src: &str, lines: &Lines, comments: &[Comment], comment: &Comment) -> Place {
+    let doc = Doc {
+        text: comment.text.to_string(),
+        span: comment.span,
+        include: None,
+        relation: None,
+        cfg: None,
+        origin: Origin::Verbatim(lines.position(comment.start + 2)),
+    };
+    let container = visitor.enclosing(comment.span);
+    let line = comment.span.start.line;
+    let line_text = lines.line(line).expect("a comment should be on a line in the file");
+    let trailing = line_text.chars().take(comment.span.start.column).any(|c| !c.is_whitespace());
+    if !trailing
+        && let Some((node, code)) = visitor.following(container, comment.span)
+        && parse::comment::is_adjacent(src, comments, comment.end, lines.offset(code))
+    {
+        return Place {
+            scope: visitor.nodes[node].scope,
+            span: visitor.nodes[node].span,
+            docs: vec![doc],
+            node,
+        };
+    }
+    Place {
+        scope: Scope::Line,
+        span: lines.line_span(line).expect("a comment should be on a line in the file"),
+        docs: vec![doc],
+        node: container,
+    }
+}
+
+/// The span of a marker in the source, found from its offset in the text of the doc. Markers
+/// that are not in the source, or whose literal cannot be decoded, have the span of the doc.
+fn marker_span(src: &str, lines: &Lines, doc: &Doc, relation: &parse::Relation) -> Span {
+    let (start, end) = (relation.offset, relation.offset + relation.length);
+    let offsets = match doc.origin {
+        Origin::Verbatim(position) => {
+            let base = lines.offset(position);
+            Some((base + start, base + end))
+        }
+        Origin::Literal(span) => {
+            let base = lines.offset(span.start);
+            let literal = &src[base..lines.offset(span.end)];
+            literal_offset(literal, start).zip(literal_offset(literal, end)).map(|(start, end)| (base + start, base + end))
+        }
+        Origin::Elsewhere => None,
+    };
+    match offsets {
+        Some((start, end)) => Span {
+            start: lines.position(start),
+            end: lines.position(end),
+        },
+        None => doc.span,
+    }
+}
+
+/// The byte offset in the source text of a string literal, such as `"caf\u{e9}\n"`, of the byte
+/// `offset` in its value, decoding escapes and line continuations as `rustc` does.
+fn literal_offset(literal: &str, offset: usize) -> Option<usize> {
+    if let Some(raw) = literal.strip_prefix('r') {
+        let start = 2 + raw.len() - raw.trim_start_matches('#').len();
+        return (start + offset < literal.len()).then_some(start + offset);
+    }
+    let mut decoded = 0;
+    let mut chars = literal.char_indices().skip(1).peekable();
+    while let Some((index, c)) = chars.next() {
+        // A line continuation is skipped along with the whitespace after it
+        if c == '\\' && chars.peek().is_some_and(|&(_, c)| c == '\n' || c == '\r') {
+            while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+            continue;
+        }
+        if decoded >= offset {
+            return (decoded == offset).then_some(index);
+        }
+        match c {
+            '"' => return None,
+            '\\' => match chars.next()?.1 {
+                'x' => {
+                    chars.nth(1)?;
+                    decoded += 1;
+                }
+                'u' => {
+                    let digits: String = chars.by_ref().map(|(_, c)| c).take_while(|&c| c != '}').filter(|c| c.is_ascii_hexdigit()).collect();
+                    decoded += char::from_u32(u32::from_str_radix(&digits, 16).ok()?)?.len_utf8();
+                }
+                _ => decoded += 1,
+            },
+            // A CRLF line ending in a literal is a single `\n` in its value
+            '\r' if chars.peek().is_some_and(|&(_, c)| c == '\n') => {}
+            c => decoded += c.len_utf8(),
+        }
+    }
+    None
+}
+
+/// A path given in a source file, such as that of an `include_str!`, is relative to the
+/// directory containing the source file.
+fn relative_to(source: &Path, path: &Path) -> PathBuf {
+    source.parent().unwrap_or(Path::new("")).join(path)
+}
+
+/// Guess the module of a source file in the conventional Cargo layout, such as `crate::storage`
+/// for `src/storage.rs` or `src/storage/mod.rs`. The path is taken from after the last `src`
+/// directory if there is one, and `lib.rs` and `main.rs` are the crate root. Use
+/// [`find_relations_in_crate`] to resolve modules properly.
+fn module_path(path: &Path) -> String {
+    let components: Vec<_> = path.components().filter_map(|component| component.as_os_str().to_str()).collect();
+    let start = components.iter().rposition(|&component| component == "src").map_or(0, |src| src + 1);
+    let mut module = vec!["crate"];
+    for (index, component) in components.iter().enumerate().skip(start) {
+        let last = index + 1 == components.len();
+        let component = if last { component.strip_suffix(".rs").unwrap_or(component) } else { component };
+        if !(last && (component == "mod" || (index == start && matches!(component, "lib" | "main")))) {
+            module.push(component);
+        }
+    }
+    module.join("::")
+}
+
+/// The fully qualified path of a node in a file with the given module path.
+fn item_path(module: &str, node: &Node) -> String {
+    std::iter::once(module).chain(node.path.iter().map(String::as_str)).collect::<Vec<_>>().join("::")
+}
+
+/// The combined condition under which a relation applies: the `#[cfg]` predicates of the code it
+/// applies to, and the `#[cfg_attr]` predicate of the doc the marker was found in.
+fn condition(node: Option<&Cfg>, doc: Option<&Cfg>) -> Option<String> {
+    let predicates: Vec<Cfg> = node.into_iter().chain(doc).cloned().collect();
+    (!predicates.is_empty()).then(|| Cfg::all(predicates).to_string())
+}
+
+/// Remove `.` and `..` components from a path without touching the file system, so that an
+/// included `../README.md` is reported as `README.md` rather than `src/../README.md`.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if matches!(normalized.components().next_back(), Some(Component::Normal(_))) => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Select the span a relation applies to, as given by its [StrictDoc `scope=`](https://strictdoc.readthedocs.io/en/stable/stable/docs/strictdoc_01_user_guide.html#10.2-Linking-source-code-to-requirements)
+/// attribute. Without one, the span is that of the place the marker was found on. Also returns
+/// the node the span was taken from, or that encloses it, for its `#[cfg]` predicate and path.
+fn select_scope(visitor: &Visitor, lines: &Lines, place: &Place, marker: Span, relation: &parse::Relation) -> Result<(Scope, Span, usize), String> {
+    let Some(scope) = relation.attributes.get("scope") else {
+        return Ok((place.scope, place.span, place.node));
+    };
+    let enclosing = |kind: &str, wanted: fn(Scope) -> bool| {
+        visitor
+            .ancestry(place.node)
+            .find(|&node| wanted(visitor.nodes[node].scope))
+            .map(|node| (visitor.nodes[node].scope, visitor.nodes[node].span, node))
+            .ok_or_else(|| format!("`scope={scope}` on {:?} is not inside a {kind} for relation {}", place.scope, relation.identifier))
+    };
+    match scope.as_str() {
+        "file" => Ok((Scope::File, lines.file_span(), 0)),
+        "class" => enclosing("type", Scope::is_class),
+        "function" => enclosing("function", Scope::is_function),
+        "line" => {
+            let span = lines.line_span(marker.start.line).expect("a marker should be on a line in the file");
+            Ok((Scope::Line, span, place.node))
+        }
+        _ => Err(format!("unknown `scope={scope}` for relation {}", relation.identifier)),
+    }
+}
+
+/// One end of a `scope=range_start` ... `scope=range_end` pair of markers, or of a legacy
+/// `@sdoc[REQ]` ... `@sdoc[/REQ]` block.
+struct Bound {
+    relation: parse::Relation,
+    /// Either [`Scope::Range`] or [`Scope::Block`].
+    scope: Scope,
+    start: bool,
+    /// The span of the marker itself.
+    marker: Span,
+    /// The span of the doc comment or attribute containing the marker.
+    doc: Span,
+    /// The condition under which the marker applies.
+    cfg: Option<String>,
+    /// The path of the node the marker was found on.
+    path: String,
+    /// The node whose code contains the marker, which should be the same for both ends.
+    container: usize,
+}
+
+impl Bound {
+    /// Markers are ordered by where they are in the source, and markers with several identifiers
+    /// share the same position.
+    fn position(&self) -> (LineColumn, usize) {
+        (self.marker.start, self.relation.offset)
+    }
+
+    /// Describe the marker at either end of this bound's pair, as it would be written.
+    fn marker(&self, start: bool) -> String {
+        let identifier = &self.relation.identifier;
+        match (self.scope, start) {
+            (Scope::Block, true) => format!("`@sdoc[{identifier}]`"),
+            (Scope::Block, false) => format!("`@sdoc[/{identifier}]`"),
+            (_, true) => format!("`scope=range_start` for relation {identifier}"),
+            (_, false) => format!("`scope=range_end` for relation {identifier}"),
+        }
+    }
+}
+
+/// Is this marker the start (`true`) or end (`false`) of a range or legacy block?
+fn range_bound(relation: &parse::Relation) -> Option<(Scope, bool)> {
+    match (relation.block, relation.attributes.get("scope").map(String::as_str)) {
+        (Some(parse::Block::Start), _) => Some((Scope::Block, true)),
+        (Some(parse::Block::End), _) => Some((Scope::Block, false)),
+        (None, Some("range_start")) => Some((Scope::Range, true)),
+        (None, Some("range_end")) => Some((Scope::Range, false)),
+        _ => None,
+    }
+}
+
+/// Pair up range and legacy block markers in source order, emitting a relation spanning from
+/// the line of each start marker to the line of its end marker. Ranges may nest, but must not
+/// cross; ranges and legacy blocks are paired independently of each other.
+fn pair_ranges(lines: &Lines, mut bounds: Vec<Bound>, relations: &mut Relations) {
+    bounds.sort_by_key(Bound::position);
+    let mut open: Vec<Bound> = vec![];
+    let mut ranges = vec![];
+    let at = |bound: &Bound| format!("{}:{}", bound.marker.start.line, bound.marker.start.column);
+    for bound in bounds {
+        if bound.start {
+            open.push(bound);
+            continue;
+        }
+        let identifier = &bound.relation.identifier;
+        let Some(index) = open
+            .iter()
+            .rposition(|start| start.scope == bound.scope && start.relation.identifier == *identifier)
+        else {
+            relations.warnings.push(Warning {
+                message: format!("{} has no matching {}", bound.marker(false), bound.marker(true)),
+                span: bound.doc,
+            });
+            continue;
+        };
+        let start = open.remove(index);
+        if let Some(crossed) = open[index..]
+            .iter()
+            .find(|other| other.scope == start.scope && other.position() != start.position())
+        {
+            relations.warnings.push(Warning {
+                message: format!(
+                    "{} (from {}) is closed by {} (at {}) while {} (from {}) is still open",
+                    start.marker(true),
+                    at(&start),
+                    bound.marker(false),
+                    at(&bound),
+                    crossed.marker(true),
+                    at(crossed)
+                ),
+                span: bound.doc,
+            });
+        }
+        if start.container != bound.container {
+            relations.warnings.push(Warning {
+                message: format!(
+                    "{} (from {}) is closed by {} (at {}) in a different item",
+                    start.marker(true),
+                    at(&start),
+                    bound.marker(false),
+                    at(&bound)
+                ),
+                span: bound.doc,
+            });
+        }
+        let start_line = lines.line_span(start.marker.start.line).expect("a marker should be on a line in the file");
+        let end_line = lines.line_span(bound.marker.start.line).expect("a marker should be on a line in the file");
+        ranges.push(Relation {
+            index: 0,
+            parent: None,
+            ident: start.relation.identifier,
+            attrs: start.relation.attributes,
+            role: start.relation.role,
+            cfg: start.cfg,
+            item: None,
+            path: start.path,
+            scope: start.scope,
+            span: Span {
+                start: start_line.start,
+                end: end_line.end,
+            },
+            docs: None,
+            header: None,
+            body: None,
+            text_hash: None,
+            token_hash: None,
+            fingerprint: None,
+            excerpt: None,
+            marker: Some(start.marker),
+            include: None,
+            inherited: None,
+        });
+    }
+    for start in open {
+        relations.warnings.push(Warning {
+            message: format!("{} has no matching {}", start.marker(true), start.marker(false)),
+            span: start.doc,
+        });
+    }
+    ranges.sort_by_key(|range| range.span);
+    relations.relations.extend(ranges);
+}
+
+/// Number the relations, and link each one to the relation whose span most closely (and
+/// strictly) encloses its own, such as a field's relation to its struct's.
+fn link_parents(relations: &mut [Relation]) {
+    let spans: Vec<Span> = relations.iter().map(|relation| relation.span).collect();
+    let encloses = |outer: Span, inner: Span| outer != inner && outer.start <= inner.start && inner.end <= outer.end;
+    for (index, relation) in relations.iter_mut().enumerate() {
+        relation.index = index;
+        relation.parent = (0..spans.len())
+            .filter(|&other| encloses(spans[other], spans[index]))
+            // Enclosing spans are nested, so the innermost one starts last and ends first
+            .min_by_key(|&other| (Reverse(spans[other].start), spans[other].end, other));
+    }
+}
+
+#[test]
+fn test_find_relations_in_str() -> Result<()> {
+    let src = "/// Struct with @relation(REQ-1, key=value)\npub struct Thing {\n    /// Field with @relation(REQ-2)\n    field: u8,\n}\n";
+    let relations = find_relations_in_str(&"thing.rs", src)?;
+    assert_eq!(relations.file, PathBuf::from("thing.rs"));
+    assert_eq!(relations.hash, Hash::from(src.as_bytes()));
+    assert_eq!(relations.relations.len(), 2);
+    assert_eq!(relations.relations[0].ident, "REQ-1");
+    assert_eq!(relations.relations[0].attrs["key"], "value");
+    assert_eq!(relations.relations[0].scope, Scope::ItemStruct);
+    assert_eq!(relations.relations[1].ident, "REQ-2");
+    assert_eq!(relations.relations[1].scope, Scope::Field);
+    assert_eq!(relations.relations[1].span.start.line.get(), 3);
+    Ok(())
+}
+
+#[test]
+fn test_report_round_trip() -> Result<()> {
+    let src = "/// @relation(REQ-1, key=value)\nfn thing() {}\n";
+    let report = Report::new(vec![find_relations_in_str(&"thing.rs", src)?]);
+    let json = serde_json::to_string(&report)?;
+    assert_eq!(Report::from_json(&json)?, report);
+    Ok(())
+}
+
+#[test]
+fn test_report_from_json_versions() -> Result<()> {
+    let src = "/// @relation(REQ-1)\nfn thing() {}\n";
+    let files = vec![find_relations_in_str(&"thing.rs", src)?];
+
+    // Version 0 files are bare arrays and are migrated
+    let json = serde_json::to_string(&files)?;
+    assert_eq!(Report::from_json(&json)?, Report::new(files.clone()));
+
+    // Newer versions are rejected
+    let mut report = Report::new(files);
+    report.version = FORMAT_VERSION + 1;
+    let json = serde_json::to_string(&report)?;
+    assert!(Report::from_json(&json).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_find_relations_scope_attribute() -> Result<()> {
+    let src = r#"//! Module docs
+pub struct Thing {
+    /// Field with @relation(REQ-1, scope=class)
+    field: u8,
+}
+
+impl Thing {
+    fn method(&self) {
+        /// Statement with @relation(REQ-2, scope=function)
+        /// and @relation(REQ-3, scope=line)
+        let _x = 1;
+    }
+}
+
+/// Struct with @relation(REQ-4, scope=file)
+struct Other {
+    /// Field with @relation(REQ-5, scope=function)
+    field: u8,
+}
+"#;
+    let relations = find_relations_in_str(&"thing.rs", src)?;
+    let found: Vec<_> = relations
+        .relations
+        .iter()
+        .map(|relation| (relation.ident.as_str(), relation.scope, relation.span.start.line.get(), relation.span.end.line.get()))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            ("REQ-1", Scope::ItemStruct, 2, 5),
+            ("REQ-2", Scope::ImplItemFn, 8, 12),
+            ("REQ-3", Scope::Line, 10, 10),
+            ("REQ-4", Scope::File, 1, 19),
+            ("REQ-5", Scope::Field, 17, 18),
+        ]
+    );
+    assert_eq!(relations.relations[2].span.start.column, 0);
+    assert_eq!(relations.relations[2].span.end.column, 44);
+    assert_eq!(relations.warnings.len(), 1);
+    assert_eq!(relations.warnings[0].span.start.line.get(), 17);
+    Ok(())
+}
+
+#[test]
+fn test_find_relations_ranges() -> Result<()> {
+    let src = r#"fn long() {
+    /// @relation(REQ-1, scope=range_start)
+    let a = 1;
+    /// @relation(REQ-2, REQ-3, scope=range_start)
+    let b = 2;
+    /// @relation(REQ-2, REQ-3, scope=range_end)
+    let c = 3;
+    /// @relation(REQ-1, scope=range_end)
+    let d = 4;
+    /// @relation(REQ-4, scope=range_start)
+    let e = 5;
+    /// @relation(REQ-5, scope=range_start)
+    let f = 6;
+    /// @relation(REQ-4, scope=range_end)
+    let g = 7;
+    /// @relation(REQ-6, scope=range_end)
+    let h = 8;
+}
+
+fn first() {
+    /// @relation(REQ-7, scope=range_start)
+    let i = 9;
+}
+
+fn second() {
+    /// @relation(REQ-7, scope=range_end)
+    let j = 10;
+}
+"#;
+    let relations = find_relations_in_str(&"long.rs", src)?;
+    let found: Vec<_> = relations
+        .relations
+        .iter()
+        .map(|relation| (relation.ident.as_str(), relation.scope, relation.span.start.line.get(), relation.span.end.line.get()))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            ("REQ-1", Scope::Range, 2, 8),
+            ("REQ-2", Scope::Range, 4, 6),
+            ("REQ-3", Scope::Range, 4, 6),
+            ("REQ-4", Scope::Range, 10, 14),
+            ("REQ-7", Scope::Range, 21, 26),
+        ]
+    );
+    assert_eq!(relations.relations[1].span.start.column, 0);
+    assert_eq!(relations.relations[1].span.end.column, 48);
+
+    // REQ-4 crosses REQ-5, REQ-6 is never started, REQ-7 ends in another function, and REQ-5 is
+    // never ended
+    let warned: Vec<_> = relations.warnings.iter().map(|warning| warning.span.start.line.get()).collect();
+    assert_eq!(warned, vec![14, 16, 26, 12]);
+    assert!(relations.warnings[2].message.ends_with("in a different item"));
+    Ok(())
+}
+
+#[test]
+fn test_find_relations_in_comments() -> Result<()> {
+    let src = r#"// Function with @relation(REQ-1)
+// and more description
+fn thing() {
+    // Statement with @relation(REQ-2)
+    let a = 1; // Trailing with @relation(REQ-3)
+    /* Block with @relation(REQ-4, scope=function) */
+    println!("{a}");
+
+    // Detached with @relation(REQ-5)
+
+    let b = "// not @relation(REQ-6)";
+    // Last with @relation(REQ-7)
+}
+"#;
+    let relations = find_relations_in_str(&"thing.rs", src)?;
+    let found: Vec<_> = relations
+        .relations
+        .iter()
+        .map(|relation| (relation.ident.as_str(), relation.scope, relation.span.start.line.get(), relation.span.end.line.get()))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            ("REQ-1", Scope::ItemFn, 3, 13),
+            ("REQ-2", Scope::Local, 5, 5),
+            ("REQ-3", Scope::Line, 5, 5),
+            ("REQ-4", Scope::ItemFn, 3, 13),
+            ("REQ-5", Scope::Line, 9, 9),
+            ("REQ-7", Scope::Line, 12, 12),
+        ]
+    );
+    assert!(relations.warnings.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_find_relations_in_comments_after_attributes() -> Result<()> {
+    let src = r#"#[test]
+// Test with @relation(REQ-1)
+fn t() {}
+
+/// Docs
+// Function with @relation(REQ-2)
+fn g() {}
+
+struct S {
+    #[allow(dead_code)]
+    // Field with @relation(REQ-3)
+    a: u8,
+}
+
+#[test]
+// Detached with @relation(REQ-4)
+
+fn u() {}
+"#;
+    let relations = find_relations_in_str(&"thing.rs", src)?;
+    let found: Vec<_> = relations
+        .relations
+        .iter()
+        .map(|relation| (relation.ident.as_str(), relation.scope, relation.span.start.line.get(), relation.span.end.line.get()))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            ("REQ-1", Scope::ItemFn, 1, 3),
+            ("REQ-2", Scope::ItemFn, 5, 7),
+            ("REQ-3", Scope::Field, 10, 12),
+            ("REQ-4", Scope::Line, 16, 16),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_find_relations_legacy_blocks() -> Result<()> {
+    let src = r#"fn legacy() {
+    // @sdoc[REQ-001]
+    let a = 1;
+    /* @sdoc[REQ-002] */
+    let b = 2;
+    // @sdoc[/REQ-002]
+    // @sdoc[/REQ-001]
+    // @sdoc[/REQ-003]
+}
+"#;
+    let relations = find_relations_in_str(&"legacy.rs", src)?;
+    let found: Vec<_> = relations
+        .relations
+        .iter()
+        .map(|relation| (relation.ident.as_str(), relation.scope, relation.span.start.line.get(), relation.span.end.line.get()))
+        .collect();
+    assert_eq!(found, vec![("REQ-001", Scope::Block, 2, 7), ("REQ-002", Scope::Block, 4, 6)]);
+    assert_eq!(relations.warnings.len(), 1);
+    assert_eq!(relations.warnings[0].message, "`@sdoc[/REQ-003]` has no matching `@sdoc[REQ-003]`");
+    Ok(())
+}
+
+#[test]
+fn test_find_relations_with_keywords() -> Result<()> {
+    let src = "/// @implements(REQ-1) @verifies(REQ-2) @relation(REQ-3)\nfn thing() {}\n";
+    let mut options = Options::default();
+    options.register("@implements=implements".parse()?);
+    options.register("@verifies=verifies".parse()?);
+    let relations = find_relations_in_str_with(&"thing.rs", src, &options)?;
+    let found: Vec<_> = relations.relations.iter().map(|relation| (relation.ident.as_str(), relation.role.as_deref())).collect();
+    assert_eq!(found, vec![("REQ-1", Some("implements")), ("REQ-2", Some("verifies")), ("REQ-3", None)]);
+
+    // Re-registering the default keyword gives it a role
+    options.register("@relation=traces".parse()?);
+    let relations = find_relations_in_str_with(&"thing.rs", src, &options)?;
+    assert_eq!(relations.relations[2].role.as_deref(), Some("traces"));
+    assert_eq!(options.keywords.len(), 3);
+    Ok(())
+}
+
+#[test]
+fn test_find_relations_cfg_attr() -> Result<()> {
+    let src = r#"#[cfg_attr(feature = "x", doc = "With @relation(REQ-7)")]
+#[cfg_attr(unix, cfg_attr(any(test, test), doc = "Nested @relation(REQ-8)", allow(dead_code)))]
+/// Plain @relation(REQ-9)
+fn thing() {}
+"#;
+    let relations = find_relations_in_str(&"thing.rs", src)?;
+    let found: Vec<_> = relations.relations.iter().map(|relation| (relation.ident.as_str(), relation.cfg.as_deref())).collect();
+    assert_eq!(found, vec![("REQ-7", Some(r#"feature = "x""#)), ("REQ-8", Some("all(test, unix)")), ("REQ-9", None)]);
+    assert!(relations.relations.iter().all(|relation| relation.scope == Scope::ItemFn));
+    Ok(())
+}
+
+#[test]
+fn test_find_relations_missing_include() -> Result<()> {
+    let src = "/// @relation(REQ-1)\n#[doc = include_str!(\"missing.md\")]\nfn thing() {}\n";
+    let relations = find_relations_in_str(&"nowhere/thing.rs", src)?;
+    assert_eq!(relations.relations.len(), 1);
+    assert_eq!(relations.warnings.len(), 1);
+    assert!(relations.warnings[0].message.starts_with("failed to read included file missing.md"));
+    assert_eq!(relations.warnings[0].span.start.line.get(), 2);
+    Ok(())
+}
+
+#[test]
+fn test_find_relations_unresolved_include() -> Result<()> {
+    let src = "#![doc = include_str!(concat!(env!(\"CARGO_MANIFEST_DIR\"), \"/README.md\"))]\n/// @relation(REQ-1)\nfn thing() {}\n";
+    let relations = find_relations_in_str(&"thing.rs", src)?;
+    assert_eq!(relations.relations.len(), 1);
+    assert_eq!(relations.warnings.len(), 1);
+    assert_eq!(
+        relations.warnings[0].message,
+        r#"cannot resolve include_str! argument `concat!(env!("CARGO_MANIFEST_DIR"),"/README.md")`"#
+    );
+    assert_eq!(relations.warnings[0].span.start.line.get(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_included_paths() {
+    let included = |source: &str, path: &str| normalize(&relative_to(Path::new(source), Path::new(path)));
+    assert_eq!(included("src/lib.rs", "docs/a.md"), PathBuf::from("src/docs/a.md"));
+    assert_eq!(included("src/lib.rs", "../README.md"), PathBuf::from("README.md"));
+    assert_eq!(included("lib.rs", "./a.md"), PathBuf::from("a.md"));
+    assert_eq!(included("lib.rs", "../../a.md"), PathBuf::from("../../a.md"));
+}
+
+#[test]
+fn test_find_relations_enclosing_cfg() -> Result<()> {
+    let src = r#"#![cfg(feature = "std")]
+
+/// @relation(REQ-1)
+fn everywhere() {}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    /// @relation(REQ-2)
+    #[cfg(test)]
+    fn check() {
+        // @relation(REQ-3)
+        let _x = 1;
+    }
+
+    #[cfg_attr(unix, doc = "@relation(REQ-4, scope=file)")]
+    struct Thing;
+}
+"#;
+    let relations = find_relations_in_str(&"thing.rs", src)?;
+    let found: Vec<_> = relations.relations.iter().map(|relation| (relation.ident.as_str(), relation.cfg.as_deref())).collect();
+    assert_eq!(
+        found,
+        vec![
+            ("REQ-1", Some(r#"feature = "std""#)),
+            ("REQ-2", Some(r#"all(test, feature = "std", target_os = "linux")"#)),
+            ("REQ-4", Some(r#"all(unix, feature = "std")"#)),
+            ("REQ-3", Some(r#"all(test, feature = "std", target_os = "linux")"#)),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_find_relations_attribute() -> Result<()> {
+    let src = r#"#[sdoc::relation("REQ-1", role = "implements")]
+fn thing() {
+    #[sdoc::relation("REQ-2", scope = "line")]
+    let _x = 1;
+}
+
+#[cfg_attr(unix, ::sdoc::relation("REQ-3"))]
+#[other::relation("REQ-4")]
+struct Other;
+"#;
+    let relations = find_relations_in_str(&"thing.rs", src)?;
+    let found: Vec<_> = relations
+        .relations
+        .iter()
+        .map(|relation| (relation.ident.as_str(), relation.role.as_deref(), relation.scope, relation.cfg.as_deref()))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            ("REQ-1", Some("implements"), Scope::ItemFn, None),
+            ("REQ-2", None, Scope::Line, None),
+            ("REQ-3", None, Scope::ItemStruct, Some("unix")),
+        ]
+    );
+    assert!(find_relations_in_str(&"thing.rs", "#[sdoc::relation(REQ_1)]\nfn thing() {}\n").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_find_relations_item_path() -> Result<()> {
+    let src = r#"pub mod storage {
+    pub struct Container<T> {
+        /// @relation(REQ-1)
+        items: Vec<T>,
+    }
+
+    impl<T> Container<T> {
+        /// @relation(REQ-2)
+        pub fn new() -> Self {
+            // @relation(REQ-3)
+            Container { items: vec![] }
+        }
+    }
+
+    impl<T> std::fmt::Debug for &Container<T> {
+        /// @relation(REQ-4, scope=class)
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str("Container") // @relation(REQ-5)
+        }
+    }
+}
+"#;
+    let relations = find_relations_in_str(&"src/lib.rs", src)?;
+    let found: Vec<_> = relations.relations.iter().map(|relation| (relation.ident.as_str(), relation.item.as_deref(), relation.path.as_str())).collect();
+    assert_eq!(
+        found,
+        vec![
+            ("REQ-1", Some("items"), "crate::storage::Container::items"),
+            ("REQ-2", Some("new"), "crate::storage::Container::new"),
+            ("REQ-4", Some("<&Container<T> as std::fmt::Debug>"), "crate::storage::<&Container<T> as std::fmt::Debug>"),
+            ("REQ-3", None, "crate::storage::Container::new"),
+            ("REQ-5", None, "crate::storage::<&Container<T> as std::fmt::Debug>::fmt"),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_find_relations_local_item_path() -> Result<()> {
+    let src = r#"struct Thing;
+
+impl Thing {
+    fn a() {
+        /// @relation(REQ-1)
+        fn inner() {}
+    }
+
+    /// @relation(REQ-2)
+    fn inner() {}
+}
+"#;
+    let relations = find_relations_in_str(&"src/d.rs", src)?;
+    let found: Vec<_> = relations.relations.iter().map(|relation| (relation.ident.as_str(), relation.path.as_str())).collect();
+    assert_eq!(found, vec![("REQ-1", "crate::d::Thing::a::inner"), ("REQ-2", "crate::d::Thing::inner")]);
+    Ok(())
+}
+
+#[test]
+fn test_module_path() {
+    let module = |path: &str| module_path(Path::new(path));
+    assert_eq!(module("src/lib.rs"), "crate");
+    assert_eq!(module("src/main.rs"), "crate");
+    assert_eq!(module("src/storage.rs"), "crate::storage");
+    assert_eq!(module("src/storage/mod.rs"), "crate::storage");
+    assert_eq!(module("crates/a/src/storage/main.rs"), "crate::storage::main");
+    assert_eq!(module("thing.rs"), "crate::thing");
+}
+
+#[test]
+fn test_find_relations_in_macros() -> Result<()> {
+    let src = r#"cfg_if::cfg_if! {
+    if #[cfg(unix)] {
+        /// @relation(REQ-1)
+        fn imp() {}
+    } else {
+        /// @relation(REQ-2)
+        fn imp() {}
+    }
+}
+
+bitflags! {
+    /// @relation(REQ-3)
+    pub struct Flags: u32 {
+        /// @relation(REQ-4)
+        const A = 1;
+    }
+}
+
+fn main() {
+    let v = vec![
+        // @relation(REQ-5)
+        1,
+    ];
+}
+
+macro_rules! wrap {
+    ($item:item) => {
+        /// @relation(NOT-CODE)
+        $item
+    };
+}
+
+fn generate(name: &Ident) -> TokenStream {
+    quote::quote! {
+        /// @relation(NOT-CODE)
+        fn #name() {}
+    }
+}
+"#;
+    let relations = find_relations_in_str(&"thing.rs", src)?;
+    let found: Vec<_> = relations
+        .relations
+        .iter()
+        .map(|relation| (relation.ident.as_str(), relation.scope, relation.span.start.line.get(), relation.span.end.line.get()))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            ("REQ-1", Scope::ItemFn, 3, 4),
+            ("REQ-2", Scope::ItemFn, 6, 7),
+            ("REQ-3", Scope::Tokens, 12, 16),
+            ("REQ-4", Scope::Tokens, 14, 15),
+            ("REQ-5", Scope::ExprLit, 22, 22),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_find_relations_parts() -> Result<()> {
+    let src = r#"/// @relation(REQ-1)
+#[inline]
+pub fn thing<T>(value: T) -> T
+where
+    T: Copy,
+{
+    value
+}
+
+/// @relation(REQ-2)
+struct Unit;
+
+struct Thing {
+    /// @relation(REQ-3, scope=class)
+    field: u8,
+}
+"#;
+    let relations = find_relations_in_str(&"thing.rs", src)?;
+    let lines = |span: Option<Span>| span.map(|span| (span.start.line.get(), span.start.column, span.end.line.get(), span.end.column));
+    let found: Vec<_> = relations
+        .relations
+        .iter()
+        .map(|relation| (relation.ident.as_str(), lines(relation.docs), lines(relation.header), lines(relation.body)))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            ("REQ-1", Some((1, 0, 2, 9)), Some((3, 0, 5, 12)), Some((6, 0, 8, 1))),
+            ("REQ-2", Some((10, 0, 10, 20)), Some((11, 0, 11, 12)), None),
+            ("REQ-3", None, Some((13, 0, 13, 12)), Some((13, 13, 16, 1))),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_find_relations_parents() -> Result<()> {
+    let src = r#"//! @relation(REQ-1)
+
+/// @relation(REQ-2) @relation(REQ-3)
+struct Thing {
+    /// @relation(REQ-4)
+    field: u8,
+}
+
+fn thing() {
+    // @relation(REQ-5, scope=range_start)
+    let _x = 1;
+    // @relation(REQ-6)
+    let _y = 2;
+    // @relation(REQ-5, scope=range_end)
+}
+"#;
+    let relations = find_relations_in_str(&"thing.rs", src)?;
+    let found: Vec<_> = relations.relations.iter().map(|relation| (relation.index, relation.ident.as_str(), relation.parent)).collect();
+    assert_eq!(
+        found,
+        vec![
+            (0, "REQ-1", None),
+            (1, "REQ-2", Some(0)),
+            (2, "REQ-3", Some(0)),
+            (3, "REQ-4", Some(1)),
+            (4, "REQ-6", Some(5)),
+            (5, "REQ-5", Some(0)),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_find_relations_parents_same_span() -> Result<()> {
+    // The file span comes from the source, with offsets, and the module's from `syn`, without
+    let src = "//! Module @relation(REQ-1)\n\n/// Thing @relation(REQ-2, scope=file)\nfn thing() {}\n";
+    let relations = find_relations_in_str(&"thing.rs", src)?;
+    let found: Vec<_> = relations.relations.iter().map(|relation| (relation.ident.as_str(), relation.parent)).collect();
+    assert_eq!(found, vec![("REQ-1", None), ("REQ-2", None)]);
+    Ok(())
+}
+
+#[test]
+fn test_find_relations_inherit() -> Result<()> {
+    let src = r#"struct Thing;
+
+/// @relation(REQ-1)
+impl Thing {
+    fn a(&self) {}
+
+    /// @relation(REQ-2)
+    fn b(&self) {}
+}
+
+mod inner {
+    //! @relation(REQ-3)
+    use std::fmt;
+
+    pub trait Trait {
+        fn c(&self);
+    }
+
+    macro_rules! m {
+        () => {};
+    }
+}
+"#;
+    let options = Options {
+        inherit: true,
+        ..Options::default()
+    };
+    let relations = find_relations_in_str_with(&"src/lib.rs", src, &options)?;
+    let found: Vec<_> = relations
+        .relations
+        .iter()
+        .map(|relation| (relation.index, relation.ident.as_str(), relation.path.as_str(), relation.inherited.map(|inherited| inherited.from)))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            (0, "REQ-1", "crate::Thing", None),
+            (1, "REQ-1", "crate::Thing::a", Some(0)),
+            (2, "REQ-1", "crate::Thing::b", Some(0)),
+            (3, "REQ-2", "crate::Thing::b", None),
+            (4, "REQ-3", "crate::inner", None),
+            (5, "REQ-3", "crate::inner::Trait", Some(4)),
+            (6, "REQ-3", "crate::inner::Trait::c", Some(4)),
+        ]
+    );
+    assert_eq!(relations.relations[1].inherited.map(|inherited| inherited.span), Some(relations.relations[0].span));
+
+    // Nothing is inherited by default
+    assert_eq!(find_relations_in_str(&"src/lib.rs", src)?.relations.len(), 3);
+    Ok(())
+}
+
+#[test]
+fn test_find_relations_columns() -> Result<()> {
+    let src = "/// Größe 😀 @relation(REQ-1, scope=line)\nfn größe() {}\n";
+    let find = |columns| {
+        let options = Options { columns, ..Options::default() };
+        find_relations_in_str_with(&"thing.rs", src, &options).map(|relations| relations.relations[0].span)
+    };
+    let chars = find(Columns::Chars)?;
+    assert_eq!((chars.start.column, chars.end.column), (0, 40));
+    assert_eq!((chars.start.offset, chars.end.offset), (0, src.find('\n').unwrap()));
+    let bytes = find(Columns::Bytes)?;
+    assert_eq!((bytes.end.column, bytes.end.offset), (chars.end.offset, chars.end.offset));
+    let utf16 = find(Columns::Utf16)?;
+    assert_eq!((utf16.end.column, utf16.end.offset), (41, chars.end.offset));
+    Ok(())
+}
+
+#[test]
+fn test_find_relations_hashes() -> Result<()> {
+    let hashes = |src: &str| -> Result<Vec<(Option<Hash>, Option<Hash>)>> {
+        let relations = find_relations_in_str(&"hash.rs", src)?;
+        Ok(relations.relations.into_iter().map(|relation| (relation.text_hash, relation.token_hash)).collect())
+    };
+    let original = hashes("/// @relation(REQ-1)\nfn one() -> u8 { 1 }\n\n/// @relation(REQ-2)\nfn two() {}\n")?;
+    assert_eq!(original[0].0, Some(Hash::from("/// @relation(REQ-1)\nfn one() -> u8 { 1 }".as_bytes())));
+
+    // Reformatting and commenting one item leaves its token hash, but not its text hash
+    let reformatted = hashes("/// @relation(REQ-1)\nfn one()->u8 {\n    1 // one\n}\n\n/// @relation(REQ-2)\nfn two() {}\n")?;
+    assert_ne!(reformatted[0].0, original[0].0);
+    assert_eq!(reformatted[0].1, original[0].1);
+    assert_eq!(reformatted[1], original[1]);
+
+    // Changing the code or the docs changes both
+    let changed = hashes("/// @relation(REQ-1)\nfn one() -> u8 { 2 }\n\n/// @relation(REQ-2) Two.\nfn two() {}\n")?;
+    assert_ne!(changed[0].0, original[0].0);
+    assert_ne!(changed[0].1, original[0].1);
+    assert_ne!(changed[1].1, original[1].1);
+    Ok(())
+}
+
+#[test]
+fn test_excerpt() {
+    let text = "fn long(\n    a: u8,\n    b: u8,\n) {\n    one();\n    two();\n}";
+    let excerpt = |lines, bytes, keep| Excerpt { lines, bytes }.of(text, keep);
+    assert_eq!(excerpt(10, 1000, 0), text);
+    assert_eq!(excerpt(2, 1000, 0), "fn long(\n    a: u8,\n// ... (5 more line(s))");
+    // The header is kept, even beyond the number of lines
+    assert_eq!(excerpt(1, 1000, 34), "fn long(\n    a: u8,\n    b: u8,\n) {\n// ... (3 more line(s))");
+    // The size limit takes precedence, on a character boundary
+    assert_eq!(excerpt(10, 5, 0), "fn lo\n// ... (7 more line(s))");
+    assert_eq!(Excerpt { lines: 1, bytes: 2 }.of("aé", 0), "a\n// ... (1 more line(s))");
+}
+
+#[test]
+fn test_literal_offset() {
+    assert_eq!(literal_offset(r#""ab""#, 1), Some(2));
+    assert_eq!(literal_offset(r#""ab""#, 2), Some(3));
+    assert_eq!(literal_offset(r#""ab""#, 3), None);
+    assert_eq!(literal_offset(r#""\n\x41\u{e9}b""#, 4), Some(13));
+    assert_eq!(literal_offset(r#""\u{e9}b""#, 1), None);
+    assert_eq!(literal_offset("\"a\\\n    b\"", 1), Some(8));
+    assert_eq!(literal_offset("\"a\r\nb\"", 2), Some(4));
+    assert_eq!(literal_offset(r###"r##"a"b"##"###, 2), Some(6));
+}
+
+#[test]
+fn test_find_relations_marker() -> Result<()> {
+    let src = r#"/// Docs @relation(REQ-1)
+fn one() {}
+
+/**
+ * Block @relation(REQ-2)
+ */
+fn two() {}
+
+#[doc = "caf\u{e9}\n@relation(REQ-3, scope=line) x"]
+fn three() {}
+
+#[cfg_attr(all(), doc = r"@relation(REQ-4)")]
+fn four() {}
+
+// @relation(REQ-5)
+fn five() {}
+
+#[sdoc::relation("REQ-6")]
+fn six() {}
+"#;
+    let relations = find_relations_in_str(&"marker.rs", src)?;
+    let found: Vec<_> = relations
+        .relations
+        .iter()
+        .map(|relation| {
+            let marker = relation.marker.expect("a marker");
+            (relation.ident.as_str(), &src[marker.start.offset..marker.end.offset], marker.start.line.get(), marker.start.column)
+        })
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            ("REQ-1", "@relation(REQ-1)", 1, 9),
+            ("REQ-2", "@relation(REQ-2)", 5, 9),
+            ("REQ-3", "@relation(REQ-3, scope=line)", 9, 20),
+            ("REQ-4", "@relation(REQ-4)", 12, 26),
+            ("REQ-6", "#[sdoc::relation(\"REQ-6\")]", 18, 0),
+            ("REQ-5", "@relation(REQ-5)", 15, 3),
+        ]
+    );
+    // The marker is on the same source line as the attribute, despite the `\n` in the literal
+    assert_eq!(relations.relations[2].span.start.line.get(), 9);
+    Ok(())
+}
+
+#[test]
+fn test_find_relations_fingerprint() -> Result<()> {
+    let fingerprints = |src: &str| -> Result<Vec<Option<Hash>>> {
+        let relations = find_relations_in_str(&"fingerprint.rs", src)?;
+        Ok(relations.relations.into_iter().map(|relation| relation.fingerprint).collect())
+    };
+    let original = r#"/// Sums the values. @relation(REQ-1)
+fn sum(values: &[u8], start: u8,) -> u8 {
+    match values { [] => {start}, _ => values.iter().fold(start, |a, b| a+b) }
+}
+
+/// @relation(REQ-2)
+fn pick(x: u8, a: u8, b: u8) -> u8 { match x { 0 => { b } _ => { a } } }
+
+/// @relation(REQ-3)
+mod m {
+    use std::{fmt::{Debug}, io};
+    use std::{io::Write, collections::{HashMap, BTreeMap}};
+}
+
+/// @relation(REQ-4)
+pub struct S<T> where T: Clone { a: Vec<T>, f: Option<Box<dyn Fn(u8)->u8>> }
+
+/// @relation(REQ-5)
+fn g() -> Vec<u8> { let v = vec![1,2,3,]; let c = |a: u8| { a + 1 }; v.into_iter().map(c).collect() }
+"#;
+    // The output of `rustfmt --edition 2024` for the original
+    let formatted = r#"/// Sums the values. @relation(REQ-1)
+fn sum(values: &[u8], start: u8) -> u8 {
+    match values {
+        [] => start,
+        _ => values.iter().fold(start, |a, b| a + b),
+    }
+}
+
+/// @relation(REQ-2)
+fn pick(x: u8, a: u8, b: u8) -> u8 {
+    match x {
+        0 => b,
+        _ => a,
+    }
+}
+
+/// @relation(REQ-3)
+mod m {
+    use std::{
+        collections::{BTreeMap, HashMap},
+        io::Write,
+    };
+    use std::{fmt::Debug, io};
+}
+
+/// @relation(REQ-4)
+pub struct S<T>
+where
+    T: Clone,
+{
+    a: Vec<T>,
+    f: Option<Box<dyn Fn(u8) -> u8>>,
+}
+
+/// @relation(REQ-5)
+fn g() -> Vec<u8> {
+    let v = vec![1, 2, 3];
+    let c = |a: u8| a + 1;
+    v.into_iter().map(c).collect()
+}
+"#;
+    assert_eq!(fingerprints(formatted)?, fingerprints(original)?);
+    // Reflowing the docs does not change the fingerprint
+    let reflowed = formatted.replace("/// Sums the values. @relation(REQ-1)", "/// Sums\n/// the values.\n/// @relation(REQ-1)");
+    assert_eq!(fingerprints(&reflowed)?, fingerprints(original)?);
+    // Changing the code changes the fingerprint of the relation on it only
+    let changed = fingerprints(&original.replace("a+b", "a*b"))?;
+    let original = fingerprints(original)?;
+    assert_ne!(changed[0], original[0]);
+    assert_eq!(changed[1..], original[1..]);
+    Ok(())
+}
+
+#[test]
+fn test_find_relations_malformed_comment() -> Result<()> {
+    let src = "// TODO: the @relation( syntax is documented elsewhere\n/* email me at foo@sdoc[bar */\n// @relation(REQ-1)\nfn thing() {}\n";
+    let relations = find_relations_in_str(&"thing.rs", src)?;
+    let found: Vec<_> = relations.relations.iter().map(|relation| relation.ident.as_str()).collect();
+    assert_eq!(found, vec!["REQ-1"]);
+    let warned: Vec<_> = relations.warnings.iter().map(|warning| (warning.span.start.line.get(), warning.message.starts_with("ignored comment with a malformed"))).collect();
+    assert_eq!(warned, vec![(1, true), (2, true)]);
+    // A malformed marker in a doc comment is still an error
+    assert!(find_relations_in_str(&"thing.rs", "/// @relation(\nfn thing() {}\n").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_find_relations_bom() -> Result<()> {
+    let src = "\u{feff}/// @relation(REQ-1)\nfn thing() {}\n";
+    let relations = find_relations_in_str(&"thing.rs", src)?;
+    let marker = relations.relations[0].marker.expect("a marker");
+    assert_eq!((marker.start.column, marker.start.offset), (4, 7));
+    assert_eq!(&src[marker.start.offset..marker.end.offset], "@relation(REQ-1)");
+    let span = relations.relations[0].span;
+    assert_eq!((span.start.column, span.start.offset), (0, 3));
+    Ok(())
+}
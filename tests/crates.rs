@@ -0,0 +1,75 @@
+use sdoc_rs_relations::{find_relations_in_crate, Options};
+
+use std::fs;
+use std::path::PathBuf;
+
+fn crate_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/crates/basic")
+}
+
+#[test]
+fn crate_modules_are_resolved_from_crate_roots() {
+    let root = crate_dir();
+    let files = find_relations_in_crate(&root, &root, &Options::default()).expect("crate resolves");
+
+    let found: Vec<_> = files
+        .iter()
+        .map(|relations| (relations.file.to_string_lossy().into_owned(), relations.module.as_deref(), relations.orphan))
+        .collect();
+    pretty_assertions::assert_eq!(
+        found,
+        vec![
+            ("build.rs".to_string(), Some("crate"), false),
+            ("src/inline/nested.rs".to_string(), Some("crate::inline::nested"), false),
+            ("src/lib.rs".to_string(), Some("crate"), false),
+            ("src/net/tcp.rs".to_string(), Some("crate::net::tcp"), false),
+            ("src/net.rs".to_string(), Some("crate::net"), false),
+            ("src/old_backup.rs".to_string(), None, true),
+            ("src/platform/linux.rs".to_string(), Some("crate::imp"), false),
+            ("src/storage/container.rs".to_string(), Some("crate::storage::container"), false),
+            ("src/storage/mod.rs".to_string(), Some("crate::storage"), false),
+        ]
+    );
+
+    let path = |file: &str| files.iter().find(|relations| relations.file.ends_with(file)).expect("file is found");
+    assert_eq!(path("container.rs").relations[0].path, "crate::storage::container::Container::new");
+    assert_eq!(path("linux.rs").relations[0].path, "crate::imp::platform");
+
+    // Orphans are reported along with their markers, and missing modules are warned about
+    let orphan = path("old_backup.rs");
+    assert_eq!(orphan.relations[0].ident, "REQ-STALE");
+    assert!(orphan.warnings[0].message.starts_with("orphan file"));
+    assert_eq!(path("lib.rs").warnings[0].message, "file not found for module `missing`");
+}
+
+#[test]
+fn crate_roots_are_read_from_the_manifest() {
+    let root = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("custom-roots");
+    let _ = fs::remove_dir_all(&root);
+    for (file, text) in [
+        ("Cargo.toml", "[package]\nname = \"custom\"\nbuild = \"scripts/generate.rs\"\n\n[lib]\npath = \"lib/root.rs\"\n\n[[bin]]\nname = \"tool\"\npath = \"tools/tool.rs\"\n"),
+        ("lib/root.rs", "mod inner;\n"),
+        ("lib/inner.rs", "/// @relation(REQ-LIB)\npub fn inner() {}\n"),
+        ("tools/tool.rs", "fn main() {}\n"),
+        ("scripts/generate.rs", "fn main() {}\n"),
+    ] {
+        let path = root.join(file);
+        fs::create_dir_all(path.parent().expect("a parent directory")).expect("directory is created");
+        fs::write(path, text).expect("file is written");
+    }
+    let files = find_relations_in_crate(&root, &root, &Options::default()).expect("crate resolves");
+
+    let found: Vec<_> = files
+        .iter()
+        .map(|relations| (relations.file.to_string_lossy().into_owned(), relations.module.as_deref(), relations.orphan))
+        .collect();
+    pretty_assertions::assert_eq!(
+        found,
+        vec![
+            ("lib/inner.rs".to_string(), Some("crate::inner"), false),
+            ("lib/root.rs".to_string(), Some("crate"), false),
+            ("scripts/generate.rs".to_string(), Some("crate"), false),
+            ("tools/tool.rs".to_string(), Some("crate"), false),
+        ]
+    );
+}
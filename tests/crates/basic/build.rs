@@ -0,0 +1,3 @@
+//! The build script is a crate root too, with @relation(REQ-BUILD)
+
+fn main() {}
@@ -0,0 +1,2 @@
+/// @relation(REQ-NESTED)
+fn nested() {}
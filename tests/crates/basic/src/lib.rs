@@ -0,0 +1,18 @@
+//! A crate laid out in all the ways `mod` declarations can find files.
+
+pub mod net;
+pub mod storage;
+
+#[cfg(target_os = "linux")]
+#[path = "platform/linux.rs"]
+mod imp;
+
+/// @relation(REQ-ROOT)
+pub fn root() {}
+
+mod inline {
+    /// Found at `src/inline/nested.rs`
+    mod nested;
+}
+
+mod missing;
@@ -0,0 +1,2 @@
+/// A `foo.rs` with submodules in the directory `foo`.
+pub mod tcp;
@@ -0,0 +1,2 @@
+/// @relation(REQ-TCP)
+pub struct Connection;
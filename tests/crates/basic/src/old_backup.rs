@@ -0,0 +1,2 @@
+/// A stray copy that is not compiled, with @relation(REQ-STALE)
+pub fn root() {}
@@ -0,0 +1,2 @@
+/// @relation(REQ-LINUX)
+pub fn platform() {}
@@ -0,0 +1,8 @@
+pub struct Container;
+
+impl Container {
+    /// @relation(REQ-NEW)
+    pub fn new() -> Self {
+        Container
+    }
+}
@@ -0,0 +1,8 @@
+//! A crate whose documentation lives in Markdown files.
+#![doc = include_str!("include.md")]
+
+/// Documented inline with @relation(REQ-INLINE)
+#[doc = include_str!("include.md")]
+pub struct Widget {
+    pub size: u32,
+}
@@ -1,4 +1,5 @@
 use assert_cmd::prelude::*;
+use sdoc_rs_relations::Report;
 use serde_json::Value;
 
 use std::fs;
@@ -100,3 +101,24 @@ fn cli_outputs_match_expected_json_for_all_inputs() {
         );
     }
 }
+
+#[test]
+fn expected_json_round_trips_through_report() {
+    let mut expected: Vec<PathBuf> = fs::read_dir(outputs_dir())
+        .expect("tests/out dir should exist")
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().map(|e| e == "json").unwrap_or(false))
+        .collect();
+    expected.sort();
+
+    for path in expected {
+        let json = fs::read_to_string(&path).unwrap_or_else(|e| panic!("Failed reading {}: {}", path.display(), e));
+        let report = Report::from_json(&json).unwrap_or_else(|e| panic!("Failed loading {}: {:#}", path.display(), e));
+
+        // Serializing the loaded report must reproduce the original JSON structure
+        let expected_json: Value = serde_json::from_str(&json).expect("expected JSON should be valid");
+        let actual_json = serde_json::to_value(&report).expect("report should serialize");
+        pretty_assertions::assert_eq!(expected_json, actual_json, "Round-trip mismatch for {}", path.display());
+    }
+}
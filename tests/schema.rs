@@ -0,0 +1,23 @@
+use assert_cmd::prelude::*;
+use serde_json::Value;
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+fn schema_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("schema/relations.schema.json")
+}
+
+#[test]
+fn printed_schema_matches_checked_in_schema() {
+    let mut cmd = Command::cargo_bin("sdoc-rs-relations").expect("binary builds");
+    let assert = cmd.arg("--print-schema").assert().success();
+
+    let actual_json: Value = serde_json::from_slice(&assert.get_output().stdout).expect("schema should be valid JSON");
+    let expected_bytes = fs::read(schema_path()).expect("checked-in schema should exist");
+    let expected_json: Value = serde_json::from_slice(&expected_bytes).expect("checked-in schema should be valid JSON");
+
+    // Regenerate with `cargo run -- --print-schema > schema/relations.schema.json`
+    pretty_assertions::assert_eq!(expected_json, actual_json, "schema/relations.schema.json is out of date");
+}